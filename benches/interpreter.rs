@@ -0,0 +1,63 @@
+// 構文木をたどる実行とバイトコードの実行の速さを比べる
+//
+// `cargo bench` で実行する．ビルドしたインタプリタをプログラムごとに起動する
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::process::{Command, Stdio};
+
+const PROGRAMS: &[(&str, &str)] = &[
+    // 音を書き出すときのように，1 サンプルごとの式を 1 秒分（48000 回）計算する
+    (
+        "sample",
+        "
+        sample(i) {
+            t = i / 48000.0;
+            x = t * 440 % 1;
+            return (x < 0.5 ? 4 * x - 1 : 3 - 4 * x) * (1 - t);
+        }
+        // 再帰を深くせずに n サンプルを足し合わせる
+        render(from, n) {
+            return n < 2 ? sample(from) : render(from, n / 2) + render(from + n / 2, n - n / 2);
+        }
+        x = render(0, 48000);
+    ",
+    ),
+    (
+        "fib",
+        "fib(n) { return n < 2 ? n : fib(n - 1) + fib(n - 2); } x = fib(24);",
+    ),
+    (
+        "list",
+        "
+        sum(xs, i: 0) { return i < len(xs) ? xs[i] + sum(xs, i + 1) : 0; }
+        xs = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        repeat(n) { return n < 1 ? 0 : sum(xs) + repeat(n / 2) + repeat(n - n / 2 - 1); }
+        x = repeat(5000);
+    ",
+    ),
+];
+
+fn run(tree: bool, code: &str) {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cryss"));
+    if tree {
+        command.arg("--tree");
+    }
+    let status = command
+        .args(["-e", code])
+        .stdin(Stdio::null())
+        .status()
+        .expect("failed to run cryss");
+    assert!(status.success());
+}
+
+fn interpreter(c: &mut Criterion) {
+    for (name, code) in PROGRAMS {
+        let mut group = c.benchmark_group(*name);
+        group.sample_size(10);
+        group.bench_function("tree", |b| b.iter(|| run(true, code)));
+        group.bench_function("vm", |b| b.iter(|| run(false, code)));
+        group.finish();
+    }
+}
+
+criterion_group!(benches, interpreter);
+criterion_main!(benches);
@@ -0,0 +1,83 @@
+// 実行する前に構文木を検査する
+
+use crate::error::Error;
+use crate::expr::{BinOp, Expr, PExpr, UnOp};
+use crate::pos;
+use crate::sentence::{PSentence, Sentence};
+
+mod test;
+
+// 代入の左辺と，増減の演算子の被演算子が代入できる式であることを確かめる
+pub fn sentence((_, sentence): &PSentence) -> Result<(), Error> {
+    match sentence {
+        Sentence::Expr(Some(expr)) | Sentence::Return(Some(expr)) => self::expr(expr),
+        Sentence::Expr(None) | Sentence::Return(None) | Sentence::Import(..) => Ok(()),
+        Sentence::Block(sentences) => sentences.iter().try_for_each(self::sentence),
+        Sentence::Function(function) => {
+            for param in &function.params {
+                if let Some(default) = &param.default {
+                    self::expr(default)?;
+                }
+            }
+            function.body.iter().try_for_each(self::sentence)
+        }
+    }
+}
+
+fn expr((_, expr): &PExpr) -> Result<(), Error> {
+    match expr {
+        Expr::Identifier(_)
+        | Expr::Integer(_)
+        | Expr::Float(_)
+        | Expr::String(_)
+        | Expr::Boolean(_) => Ok(()),
+        Expr::UnOp(op, operand) => {
+            if let UnOp::PreIncrement
+            | UnOp::PreDecrement
+            | UnOp::PostIncrement
+            | UnOp::PostDecrement = op.1
+            {
+                assignable(&op.0, operand)?;
+            }
+            self::expr(operand)
+        }
+        Expr::BinOp(op, left, right) => {
+            if op.1 == BinOp::Assign || op.1.compound().is_some() {
+                assignable(&op.0, left)?;
+            }
+            self::expr(left)?;
+            self::expr(right)
+        }
+        Expr::Group(inner) => self::expr(inner),
+        Expr::Conditional(cond, then, otherwise) => {
+            self::expr(cond)?;
+            self::expr(then)?;
+            self::expr(otherwise)
+        }
+        Expr::Comparison(first, rest) => {
+            self::expr(first)?;
+            rest.iter().try_for_each(|(_, operand)| self::expr(operand))
+        }
+        Expr::Call(fnc, args, named_args) => {
+            self::expr(fnc)?;
+            args.iter().try_for_each(self::expr)?;
+            named_args.iter().try_for_each(|(_, arg)| self::expr(arg))
+        }
+        Expr::List(elems) => elems.iter().try_for_each(self::expr),
+        Expr::Index(container, index) => {
+            self::expr(container)?;
+            self::expr(index)
+        }
+        Expr::Member(object, _) => self::expr(object),
+        Expr::Record(fields) => fields.iter().try_for_each(|(_, value)| self::expr(value)),
+    }
+}
+
+// 変数か，代入できる式に添字やフィールドを付けた式なら代入できる
+fn assignable(op: &pos::Range, (pos, target): &PExpr) -> Result<(), Error> {
+    match target {
+        Expr::Identifier(_) => Ok(()),
+        Expr::Index(inner, _) | Expr::Member(inner, _) => assignable(op, inner),
+        _ => Err(Error::InvalidAssignTarget(op.clone(), pos.clone())),
+    }
+}
@@ -0,0 +1,35 @@
+#![cfg(test)]
+
+use crate::error::Error;
+use crate::lexer::Lexer;
+use crate::parser;
+
+fn check(input: &str) -> Result<(), Error> {
+    let mut lexer = Lexer::from_str("test", input);
+    while let Some((pos, sentence)) = parser::parse_sentence(&mut lexer)? {
+        super::sentence(&(pos, sentence.into()))?;
+    }
+    Ok(())
+}
+
+// 代入できない左辺の位置と演算子の位置
+fn invalid_target(input: &str) -> String {
+    match check(input) {
+        Err(Error::InvalidAssignTarget(op, target)) => format!("{op:?} {target:?}"),
+        _ => panic!("{input} was accepted"),
+    }
+}
+
+#[test]
+fn assignable() {
+    assert!(check("x = 1; xs[0] = 1; p.a[1].b += 2; x++; --p.n;").is_ok());
+    assert_eq!(invalid_target("1 + 2 = 3;"), "0:6-0:7 0:0-0:5");
+    assert_eq!(invalid_target("f(x) += 1;"), "0:5-0:7 0:0-0:4");
+    assert_eq!(invalid_target("f().a = 1;"), "0:6-0:7 0:0-0:3");
+    assert_eq!(invalid_target("(x) = 1;"), "0:4-0:5 0:0-0:3");
+    assert_eq!(invalid_target("3++;"), "0:1-0:3 0:0-0:1");
+    assert_eq!(
+        invalid_target("f(a) { return g(b = 1, (c) = 2); }"),
+        "0:27-0:28 0:23-0:26"
+    );
+}
@@ -0,0 +1,61 @@
+use std::io::Write;
+
+use crate::error::Error;
+use crate::lexer::Lexer;
+use crate::token::Token;
+
+// 字句解析の結果を 1 行に 1 トークンずつ出力する
+pub fn tokens(lexer: &mut Lexer, out: &mut impl Write) -> Result<(), Error> {
+    while let Some((range, token)) = lexer.next()? {
+        writeln!(out, "{range}\t{token:?}").expect("failed to write output");
+    }
+    Ok(())
+}
+
+// 字句解析の結果を JSON の配列として出力する
+//
+// 途中でエラーになったときは何も出力しない
+pub fn tokens_json(lexer: &mut Lexer, out: &mut impl Write) -> Result<(), Error> {
+    let mut elements = Vec::new();
+    while let Some((range, token)) = lexer.next()? {
+        let mut element = format!(
+            r#"{{"kind":{},"text":{},"range":{}"#,
+            json_string(&token.kind()),
+            json_string(&range.text()),
+            range.to_json()
+        );
+        if let Token::Identifier(s)
+        | Token::BinInt(s)
+        | Token::OctInt(s)
+        | Token::DecInt(s)
+        | Token::HexInt(s)
+        | Token::Float(s)
+        | Token::String(s) = &token
+        {
+            element += &format!(r#","value":{}"#, json_string(s));
+        }
+        element.push('}');
+        elements.push(element);
+    }
+    writeln!(out, "[{}]", elements.join(",\n")).expect("failed to write output");
+    Ok(())
+}
+
+fn json_string(s: &str) -> String {
+    let mut ret = String::from('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            '\0'..='\x1f' => ret.push_str(&format!("\\u{:04x}", ch as u32)),
+            _ => ret.push(ch),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+mod test;
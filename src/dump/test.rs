@@ -0,0 +1,57 @@
+#![cfg(test)]
+
+use crate::lexer::Lexer;
+
+fn dump(input: &str, f: fn(&mut Lexer, &mut Vec<u8>) -> Result<(), crate::error::Error>) -> String {
+    let mut lexer = Lexer::from_str("test", input);
+    let mut out = Vec::new();
+    if f(&mut lexer, &mut out).is_err() {
+        panic!("failed to lex");
+    }
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn tokens() {
+    let output = dump("f(0x1F, \"a\");", super::tokens);
+    assert_eq!(
+        output,
+        "test:1:1-1:1\tIdentifier(\"f\")
+test:1:2-1:2\tOpeningParenthesis
+test:1:3-1:6\tHexInt(\"1F\")
+test:1:7-1:7\tComma
+test:1:9-1:11\tString(\"a\")
+test:1:12-1:12\tClosingParenthesis
+test:1:13-1:$\tSemicolon
+"
+    );
+}
+
+#[test]
+fn tokens_json() {
+    let output = dump("x += \"a\\\"\n\";", |lexer, out| {
+        super::tokens_json(lexer, out)
+    });
+    assert_eq!(
+        output,
+        r#"[{"kind":"Identifier","text":"x","range":{"start":{"line":0,"column":0},"end":{"line":0,"column":1}},"value":"x"},
+{"kind":"PlusEqual","text":"+=","range":{"start":{"line":0,"column":2},"end":{"line":0,"column":4}}},
+{"kind":"String","text":"\"a\\\"\n\"","range":{"start":{"line":0,"column":5},"end":{"line":1,"column":1}},"value":"a\"\n"},
+{"kind":"Semicolon","text":";","range":{"start":{"line":1,"column":1},"end":{"line":1,"column":null}}}]
+"#
+    );
+}
+
+// text は値を書き直さず，入力のとおりに出力する
+#[test]
+fn tokens_json_text() {
+    let output = dump("\"a\\\nb\" 0x1F", |lexer, out| {
+        super::tokens_json(lexer, out)
+    });
+    assert_eq!(
+        output,
+        r#"[{"kind":"String","text":"\"a\\\nb\"","range":{"start":{"line":0,"column":0},"end":{"line":1,"column":2}},"value":"ab"},
+{"kind":"HexInt","text":"0x1F","range":{"start":{"line":1,"column":3},"end":{"line":1,"column":null}},"value":"1F"}]
+"#
+    );
+}
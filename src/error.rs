@@ -12,70 +12,354 @@ pub enum Error {
     NoClosingParenthesis(pos::Range),
     UnexpectedTokenInParenthesis(pos::Range, pos::Range),
     EmptyParenthesis(pos::Range, pos::Range),
+    NoClosingBracket(pos::Range),
+    UnexpectedTokenInBracket(pos::Range, pos::Range),
+    EmptyIndex(pos::Range, pos::Range),
+    UnexpectedEOFAfterDot(pos::Range),
+    UnexpectedTokenAfterDot(pos::Range, pos::Range),
+    UnexpectedEOFInConditional(pos::Range),
+    UnexpectedTokenInConditional(pos::Range, pos::Range),
     NoExpressionBeforeComma(pos::Range),
+    UnexpectedToken(pos::Range),
+    NoSemicolon(pos::Range),
+    UnexpectedEOFAfterImport(pos::Range),
+    UnexpectedTokenAfterImport(pos::Range, pos::Range),
+    UnexpectedEOFAfterAs(pos::Range),
+    UnexpectedTokenAfterAs(pos::Range, pos::Range),
+    NoClosingBrace(pos::Range),
+    UnexpectedTokenInBrace(pos::Range, pos::Range),
+    NoFieldName(pos::Range),
+    DuplicateField(pos::Range),
+    InvalidFunctionName(pos::Range),
+    InvalidParameter(pos::Range),
+    DuplicateParameter(pos::Range),
+    NoArgumentAfterColon(pos::Range),
+    InvalidArgumentName(pos::Range),
+    PositionalArgumentAfterNamed(pos::Range),
+    UndefinedVariable(pos::Range),
+    InvalidOperand(pos::Range, pos::Range, &'static str),
+    InvalidOperands(pos::Range, &'static str, &'static str),
+    DivisionByZero(pos::Range, pos::Range),
+    ShiftOutOfRange(pos::Range, pos::Range, i32),
+    InvalidAssignTarget(pos::Range, pos::Range),
+    NotCallable(pos::Range, &'static str),
+    InvalidCondition(pos::Range, &'static str),
+    NotIndexable(pos::Range, &'static str),
+    InvalidIndex(pos::Range, &'static str),
+    IndexOutOfRange(pos::Range, i32, usize),
+    UndefinedMember(pos::Range, &'static str),
+    TooManyArguments(pos::Range, usize, usize),
+    UnknownArgument(pos::Range),
+    DuplicateArgument(pos::Range),
+    MissingArgument(pos::Range, String),
+    InvalidArgument(pos::Range, &'static str),
+    InvalidChannels(pos::Range, usize),
+    TooManyRepetitions(pos::Range, i64),
+    TooLongSound(pos::Range),
+    ReturnOutsideFunction(pos::Range),
+    TooDeepRecursion(pos::Range),
+    CannotOpenFile(pos::Range, String, String),
+    CannotWriteFile(pos::Range, String, String),
+    ImportCycle(pos::Range, Vec<String>),
+    InvalidModuleName(pos::Range, String),
+    ModuleNameConflict(pos::Range, String),
+    InImportedFile(pos::Range, Box<Error>),
 }
 
 impl Error {
-    pub fn eprint(&self, log: &[String]) {
+    pub fn eprint(&self) {
+        if let Error::InImportedFile(pos, error) = self {
+            error.eprint();
+            eprintln!("in the file imported at {pos}");
+            pos.eprint();
+            return;
+        }
         eprint!("error: ");
         match self {
             Error::UnexpectedCharacter(pos) => {
                 eprintln!("unexpected character at {pos}");
-                pos.eprint(log);
+                pos.eprint();
             }
             Error::InvalidNumericLiteral(pos) => {
                 eprintln!("invalid numeric literal at {pos}");
-                pos.eprint(log);
+                pos.eprint();
             }
             Error::UnterminatedComment(poss) => {
                 eprintln!("unterminated comment");
                 for pos in poss {
                     eprintln!("started at {pos}");
-                    pos.eprint(log);
+                    pos.eprint();
                 }
             }
             Error::UnterminatedStringLiteral(pos) => {
                 eprintln!("unterminated string literal started at {pos}");
-                pos.eprint(log);
+                pos.eprint();
             }
             Error::UnexpectedEOFAfterPrefixOperator(pos) => {
                 eprintln!("unexpected EOF after prefix operator at {pos}");
             }
             Error::UnexpectedTokenAfterPrefixOperator(prefix, token) => {
                 eprintln!("unexpected token at {token}");
-                token.eprint(log);
+                token.eprint();
                 eprintln!("after prefix operator at {prefix}");
-                prefix.eprint(log);
+                prefix.eprint();
             }
             Error::NoClosingParenthesis(open) => {
                 eprintln!("no closing parenthesis (opened at {open})");
-                open.eprint(log);
+                open.eprint();
             }
             Error::UnexpectedTokenInParenthesis(open, token) => {
                 eprintln!("unexpected token at {token}");
-                token.eprint(log);
+                token.eprint();
                 eprintln!("parenthesis opened at {open}");
-                open.eprint(log);
+                open.eprint();
             }
             Error::EmptyParenthesis(open, close) => {
                 eprintln!("empty parenthesis (opened at {open})");
-                open.eprint(log);
+                open.eprint();
+                eprintln!("(closed at {close})");
+                close.eprint();
+            }
+            Error::NoClosingBracket(open) => {
+                eprintln!("no closing bracket (opened at {open})");
+                open.eprint();
+            }
+            Error::UnexpectedTokenInBracket(open, token) => {
+                eprintln!("unexpected token at {token}");
+                token.eprint();
+                eprintln!("bracket opened at {open}");
+                open.eprint();
+            }
+            Error::EmptyIndex(open, close) => {
+                eprintln!("empty index (opened at {open})");
+                open.eprint();
                 eprintln!("(closed at {close})");
-                close.eprint(log);
+                close.eprint();
+            }
+            Error::UnexpectedEOFAfterDot(dot) => {
+                eprintln!("unexpected EOF after dot at {dot}");
+            }
+            Error::UnexpectedTokenAfterDot(dot, token) => {
+                eprintln!("unexpected token at {token} (expected a member name)");
+                token.eprint();
+                eprintln!("after dot at {dot}");
+                dot.eprint();
+            }
+            Error::UnexpectedEOFInConditional(question) => {
+                eprintln!("unexpected EOF in conditional expression at {question}");
+            }
+            Error::UnexpectedTokenInConditional(question, token) => {
+                eprintln!("unexpected token at {token}");
+                token.eprint();
+                eprintln!("in conditional expression at {question}");
+                question.eprint();
             }
             Error::UnexpectedEOFAfterBinaryOperator(op) => {
                 eprintln!("unexpected EOF after binary operator at {op}");
             }
             Error::UnexpectedTokenAfterBinaryOperator(op, token) => {
                 eprintln!("unexpected token at {token}");
-                token.eprint(log);
+                token.eprint();
                 eprintln!("after binary operator at {op}");
-                op.eprint(log);
+                op.eprint();
             }
             Error::NoExpressionBeforeComma(comma) => {
                 eprintln!("no expression before comma at {comma}");
-                comma.eprint(log);
+                comma.eprint();
+            }
+            Error::UnexpectedToken(token) => {
+                eprintln!("unexpected token at {token}");
+                token.eprint();
+            }
+            Error::NoSemicolon(sentence) => {
+                eprintln!("no semicolon after sentence at {sentence}");
+                sentence.eprint();
+            }
+            Error::UnexpectedEOFAfterImport(import) => {
+                eprintln!("unexpected EOF after import at {import}");
+            }
+            Error::UnexpectedTokenAfterImport(import, token) => {
+                eprintln!("unexpected token at {token} (expected a path string)");
+                token.eprint();
+                eprintln!("after import at {import}");
+                import.eprint();
+            }
+            Error::UnexpectedEOFAfterAs(pos_as) => {
+                eprintln!("unexpected EOF after as at {pos_as}");
+            }
+            Error::UnexpectedTokenAfterAs(pos_as, token) => {
+                eprintln!("unexpected token at {token} (expected a module name)");
+                token.eprint();
+                eprintln!("after as at {pos_as}");
+                pos_as.eprint();
+            }
+            Error::NoClosingBrace(open) => {
+                eprintln!("no closing brace (opened at {open})");
+                open.eprint();
+            }
+            Error::UnexpectedTokenInBrace(open, token) => {
+                eprintln!("unexpected token at {token}");
+                token.eprint();
+                eprintln!("brace opened at {open}");
+                open.eprint();
+            }
+            Error::NoFieldName(field) => {
+                eprintln!("no field name for the value at {field}");
+                field.eprint();
+            }
+            Error::DuplicateField(name) => {
+                eprintln!("duplicate field at {name}");
+                name.eprint();
+            }
+            Error::InvalidFunctionName(pos) => {
+                eprintln!("invalid function name at {pos}");
+                pos.eprint();
+            }
+            Error::InvalidParameter(pos) => {
+                eprintln!("invalid parameter at {pos}");
+                pos.eprint();
+            }
+            Error::DuplicateParameter(pos) => {
+                eprintln!("duplicate parameter at {pos}");
+                pos.eprint();
+            }
+            Error::NoArgumentAfterColon(colon) => {
+                eprintln!("no argument after colon at {colon}");
+                colon.eprint();
+            }
+            Error::InvalidArgumentName(pos) => {
+                eprintln!("invalid argument name at {pos}");
+                pos.eprint();
+            }
+            Error::PositionalArgumentAfterNamed(pos) => {
+                eprintln!("positional argument after named arguments at {pos}");
+                pos.eprint();
+            }
+            Error::UndefinedVariable(pos) => {
+                eprintln!("undefined variable at {pos}");
+                pos.eprint();
+            }
+            Error::InvalidOperand(op, operand, operand_type) => {
+                eprintln!("invalid operand of type {operand_type} at {operand}");
+                operand.eprint();
+                eprintln!("for operator at {op}");
+                op.eprint();
+            }
+            Error::InvalidOperands(pos, left_type, right_type) => {
+                eprintln!("invalid operands of type {left_type} and {right_type} at {pos}");
+                pos.eprint();
+            }
+            Error::DivisionByZero(op, divisor) => {
+                eprintln!("division by zero at {op}");
+                op.eprint();
+                eprintln!("divisor at {divisor}");
+                divisor.eprint();
+            }
+            Error::ShiftOutOfRange(op, count, n) => {
+                eprintln!("shift count {n} out of range at {op}");
+                op.eprint();
+                eprintln!("count at {count}");
+                count.eprint();
+            }
+            Error::InvalidAssignTarget(op, target) => {
+                eprintln!("cannot assign to the expression at {target}");
+                target.eprint();
+                eprintln!("by the operator at {op}");
+                op.eprint();
+            }
+            Error::NotCallable(fnc, fnc_type) => {
+                eprintln!("value of type {fnc_type} at {fnc} is not callable");
+                fnc.eprint();
+            }
+            Error::InvalidCondition(cond, cond_type) => {
+                eprintln!("invalid condition of type {cond_type} at {cond}");
+                cond.eprint();
+            }
+            Error::NotIndexable(container, container_type) => {
+                eprintln!("value of type {container_type} at {container} cannot be indexed");
+                container.eprint();
+            }
+            Error::InvalidIndex(index, index_type) => {
+                eprintln!("index of type {index_type} at {index} is not an integer");
+                index.eprint();
+            }
+            Error::IndexOutOfRange(index, n, len) => {
+                eprintln!("index {n} at {index} is out of range for length {len}");
+                index.eprint();
+            }
+            Error::UndefinedMember(name, object_type) => {
+                eprintln!("undefined member at {name} of value of type {object_type}");
+                name.eprint();
+            }
+            Error::TooManyArguments(call, expected, found) => {
+                eprintln!("expected at most {expected} argument(s) but found {found} at {call}");
+                call.eprint();
+            }
+            Error::UnknownArgument(name) => {
+                eprintln!("unknown argument name at {name}");
+                name.eprint();
+            }
+            Error::DuplicateArgument(name) => {
+                eprintln!("argument given more than once at {name}");
+                name.eprint();
+            }
+            Error::MissingArgument(call, name) => {
+                eprintln!("missing argument `{name}` at {call}");
+                call.eprint();
+            }
+            Error::InvalidArgument(arg, arg_type) => {
+                eprintln!("invalid argument of type {arg_type} at {arg}");
+                arg.eprint();
+            }
+            Error::InvalidChannels(arg, channels) => {
+                eprintln!("invalid sound with {channels} channel(s) at {arg}");
+                arg.eprint();
+            }
+            Error::TooManyRepetitions(arg, times) => {
+                eprintln!("too many repetitions ({times}) at {arg}");
+                arg.eprint();
+            }
+            Error::TooLongSound(pos) => {
+                eprintln!("sound too long at {pos}");
+                pos.eprint();
+            }
+            Error::ReturnOutsideFunction(pos) => {
+                eprintln!("return outside function at {pos}");
+                pos.eprint();
+            }
+            Error::TooDeepRecursion(call) => {
+                eprintln!("too deep recursion at {call}");
+                call.eprint();
+            }
+            Error::CannotOpenFile(pos, path, message) => {
+                eprintln!("cannot open {path}: {message}");
+                pos.eprint();
+            }
+            Error::CannotWriteFile(pos, path, message) => {
+                eprintln!("cannot write {path}: {message}");
+                pos.eprint();
+            }
+            Error::ImportCycle(pos, cycle) => {
+                eprintln!("import cycle at {pos}");
+                pos.eprint();
+                for file in cycle {
+                    eprintln!("{file} imports");
+                }
+                if let Some(file) = cycle.first() {
+                    eprintln!("{file}");
+                }
+            }
+            Error::InvalidModuleName(path, name) => {
+                eprintln!("module name `{name}` is not an identifier at {path}");
+                path.eprint();
+                eprintln!("(give a name with `as`)");
+            }
+            Error::ModuleNameConflict(pos, name) => {
+                eprintln!("another module is already bound to `{name}` at {pos}");
+                pos.eprint();
+                eprintln!("(give a different name with `as`)");
             }
+            Error::InImportedFile(..) => unreachable!(),
         }
     }
 }
@@ -0,0 +1,506 @@
+use std::num::NonZero;
+use std::rc::Rc;
+
+use crate::error::Error;
+use crate::pos;
+use crate::sound::{self, Op, Signal, Sound};
+use crate::tempo::Tempo;
+use crate::value::{Builtin, PValue, Value};
+
+// repeat と loop で並べられる回数の上限（すべての回を先にグラフに置くので）
+const MAX_REPETITIONS: i64 = 1 << 16;
+
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "print",
+        params: None,
+        fnc: print,
+    },
+    Builtin {
+        name: "len",
+        params: Some(&[("list", false)]),
+        fnc: len,
+    },
+    Builtin {
+        name: "sine",
+        params: Some(&[("frequency", false), ("duration", true)]),
+        fnc: sine,
+    },
+    Builtin {
+        name: "line",
+        params: Some(&[("from", false), ("to", false), ("duration", false)]),
+        fnc: line,
+    },
+    Builtin {
+        name: "stereo",
+        params: Some(&[("left", false), ("right", false)]),
+        fnc: stereo,
+    },
+    Builtin {
+        name: "pan",
+        params: Some(&[("sound", false), ("position", false)]),
+        fnc: pan,
+    },
+    Builtin {
+        name: "channels",
+        params: Some(&[("sound", false)]),
+        fnc: channels,
+    },
+    Builtin {
+        name: "merge",
+        params: Some(&[("sounds", false)]),
+        fnc: merge,
+    },
+    Builtin {
+        name: "seq",
+        params: Some(&[("sounds", false), ("step", true)]),
+        fnc: seq,
+    },
+    Builtin {
+        name: "par",
+        params: Some(&[("sounds", false)]),
+        fnc: par,
+    },
+    Builtin {
+        name: "repeat",
+        params: Some(&[("sound", false), ("times", false)]),
+        fnc: repeat,
+    },
+    Builtin {
+        name: "loop",
+        params: Some(&[("sound", false), ("duration", false)]),
+        fnc: r#loop,
+    },
+    Builtin {
+        name: "tempo",
+        params: Some(&[("bpm", false), ("at", true), ("ramp", true)]),
+        fnc: tempo,
+    },
+    Builtin {
+        name: "meter",
+        params: Some(&[("beats", false)]),
+        fnc: meter,
+    },
+    Builtin {
+        name: "beats",
+        params: Some(&[("beats", false), ("from", true)]),
+        fnc: beats,
+    },
+    Builtin {
+        name: "bars",
+        params: Some(&[("bars", false), ("from", true)]),
+        fnc: bars,
+    },
+    Builtin {
+        name: "write",
+        params: Some(&[
+            ("sound", false),
+            ("path", false),
+            ("threads", true),
+            ("rate", true),
+        ]),
+        fnc: write,
+    },
+    Builtin {
+        name: "read",
+        params: Some(&[("path", false)]),
+        fnc: read,
+    },
+];
+
+pub fn get(name: &str) -> Option<Builtin> {
+    BUILTINS
+        .iter()
+        .find(|builtin| builtin.name == name)
+        .copied()
+}
+
+// 組み込み関数の呼び出しをまたいで保つ状態
+pub struct Context {
+    // 拍や小節を秒数に直すときのテンポ
+    tempo: Tempo,
+}
+
+impl Context {
+    pub fn new() -> Context {
+        Context {
+            tempo: Tempo::new(),
+        }
+    }
+}
+
+// 引数を空白区切りで出力する
+fn print(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let args: Vec<_> = args
+        .iter()
+        .flatten()
+        .map(|(_, arg)| arg.to_string())
+        .collect();
+    println!("{}", args.join(" "));
+    Ok(Value::Void)
+}
+
+fn len(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    match &args[0] {
+        Some((_, Value::List(elems))) => Ok(Value::Integer(elems.len() as i32)),
+        Some((pos, value)) => Err(Error::InvalidArgument(pos.clone(), value.type_name())),
+        None => unreachable!(),
+    }
+}
+
+// 正弦波
+//
+// 周波数が音なら位相をサンプルごとに進める（長さを省略すると周波数の音と同じ長さ）．
+// 周波数の音のチャンネルごとに正弦波を作る
+fn sine(_: &mut Context, pos: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let mut args = args.into_iter();
+    let frequency = args.next().unwrap().unwrap();
+    let duration = args.next().unwrap().map(|arg| length(&arg)).transpose()?;
+    let sound = match frequency {
+        (_, Value::Sound(frequency)) => {
+            let len = duration.unwrap_or(frequency.len());
+            frequency.map(|frequency| Signal::fm(frequency, len))
+        }
+        frequency => {
+            let frequency = number(&frequency)?;
+            let len = duration
+                .ok_or_else(|| Error::MissingArgument(pos.clone(), "duration".to_owned()))?;
+            Sound::mono(Signal::sine(frequency, len))
+        }
+    };
+    Ok(Value::Sound(Rc::new(sound)))
+}
+
+// from から to まで直線的に変わる音
+fn line(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let mut args = args.into_iter().flatten();
+    let from = number(&args.next().unwrap())?;
+    let to = number(&args.next().unwrap())?;
+    let len = length(&args.next().unwrap())?;
+    let sound = Sound::mono(Signal::line(from, to, len));
+    Ok(Value::Sound(Rc::new(sound)))
+}
+
+// 2 つのモノラルの音を左右のチャンネルにする
+fn stereo(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let channels = args
+        .into_iter()
+        .flatten()
+        .map(|arg| Ok(mono(&arg)?.clone()))
+        .collect::<Result<_, Error>>()?;
+    Ok(Value::Sound(Rc::new(Sound::from_channels(channels))))
+}
+
+// 位置（-1 で左，1 で右）に音を置く
+//
+// モノラルの音は等パワーで左右に分け，ステレオの音は遠い方のチャンネルを弱める
+fn pan(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let mut args = args.into_iter().flatten();
+    let (pos, sound) = match args.next().unwrap() {
+        (pos, Value::Sound(sound)) => (pos, sound),
+        (pos, value) => return Err(Error::InvalidArgument(pos, value.type_name())),
+    };
+    let position = number(&args.next().unwrap())?.clamp(-1., 1.);
+    let (left, right) = match sound.channels() {
+        [mono] => {
+            let angle = (position + 1.) * std::f64::consts::FRAC_PI_4;
+            (
+                mono.left(Op::Mul, angle.cos()),
+                mono.left(Op::Mul, angle.sin()),
+            )
+        }
+        [left, right] => (
+            left.left(Op::Mul, (1. - position).min(1.)),
+            right.left(Op::Mul, (1. + position).min(1.)),
+        ),
+        channels => return Err(Error::InvalidChannels(pos, channels.len())),
+    };
+    Ok(Value::Sound(Rc::new(Sound::from_channels(vec![
+        left, right,
+    ]))))
+}
+
+// チャンネルごとのモノラルの音のリスト
+fn channels(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    match args.into_iter().flatten().next().unwrap() {
+        (_, Value::Sound(sound)) => Ok(Value::List(Rc::new(
+            sound
+                .channels()
+                .iter()
+                .map(|signal| Value::Sound(Rc::new(Sound::mono(signal.clone()))))
+                .collect(),
+        ))),
+        (pos, value) => Err(Error::InvalidArgument(pos, value.type_name())),
+    }
+}
+
+// 音のリストのチャンネルを順に並べて 1 つの音にする
+fn merge(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let (pos, sounds) = match args.into_iter().flatten().next().unwrap() {
+        (pos, Value::List(sounds)) => (pos, sounds),
+        (pos, value) => return Err(Error::InvalidArgument(pos, value.type_name())),
+    };
+    let mut channels = Vec::new();
+    for sound in sounds.iter() {
+        match sound {
+            Value::Sound(sound) => channels.extend_from_slice(sound.channels()),
+            value => return Err(Error::InvalidArgument(pos, value.type_name())),
+        }
+    }
+    if channels.is_empty() {
+        return Err(Error::InvalidChannels(pos, 0));
+    }
+    Ok(Value::Sound(Rc::new(Sound::from_channels(channels))))
+}
+
+// 音のリストを順に並べる
+//
+// 数の要素はその秒数の休み．step を与えると，各要素を step 秒ずつの枠の始めに置く（数は空の枠）
+fn seq(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let mut args = args.into_iter();
+    let (pos, elems) = match args.next().unwrap().unwrap() {
+        (pos, Value::List(elems)) => (pos, elems),
+        (pos, value) => return Err(Error::InvalidArgument(pos, value.type_name())),
+    };
+    let step = args.next().unwrap().map(|arg| length(&arg)).transpose()?;
+    let mut sounds = Vec::new();
+    let mut time = 0;
+    for elem in elems.iter() {
+        let len = match elem {
+            Value::Sound(sound) => {
+                sounds.push(sound.shift(time));
+                sound.len()
+            }
+            rest => length(&(pos.clone(), rest.clone()))?,
+        };
+        time = time
+            .checked_add(step.unwrap_or(len))
+            .ok_or_else(|| Error::TooLongSound(pos.clone()))?;
+    }
+    Ok(Value::Sound(Rc::new(mix(&pos, sounds)?)))
+}
+
+// 音のリストを同時に鳴らす
+fn par(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let (pos, elems) = match args.into_iter().flatten().next().unwrap() {
+        (pos, Value::List(elems)) => (pos, elems),
+        (pos, value) => return Err(Error::InvalidArgument(pos, value.type_name())),
+    };
+    let sounds = elems
+        .iter()
+        .map(|elem| match elem {
+            Value::Sound(sound) => Ok((**sound).clone()),
+            value => Err(Error::InvalidArgument(pos.clone(), value.type_name())),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Value::Sound(Rc::new(mix(&pos, sounds)?)))
+}
+
+// 音を times 回続けて鳴らす
+fn repeat(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let mut args = args.into_iter().flatten();
+    let (pos, sound) = sound(args.next().unwrap())?;
+    let (times_pos, times) = match args.next().unwrap() {
+        (pos, Value::Integer(n)) if n > 0 => (pos, n as i64),
+        (pos, value) => return Err(Error::InvalidArgument(pos, value.type_name())),
+    };
+    let sounds = repetitions(&times_pos, &sound, times)?;
+    Ok(Value::Sound(Rc::new(mix(&pos, sounds)?)))
+}
+
+// 長さ duration になるまで音を繰り返し，はみ出した分は切る
+fn r#loop(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let mut args = args.into_iter().flatten();
+    let (pos, sound) = sound(args.next().unwrap())?;
+    let duration = args.next().unwrap();
+    let (duration_pos, duration) = (duration.0.clone(), length(&duration)?);
+    // 長さのない音は 1 回だけ置く
+    let times = match sound.len() {
+        0 => 1,
+        len => (duration as u64)
+            .div_ceil(len as u64)
+            .clamp(1, i64::MAX as u64) as i64,
+    };
+    let sounds = repetitions(&duration_pos, &sound, times)?;
+    Ok(Value::Sound(Rc::new(mix(&pos, sounds)?.take(duration))))
+}
+
+// テンポ（1 分あたりの拍数）を決める
+//
+// at を省略すると始めから終わりまで一定にし，与えるとその拍から変える．
+// ramp が真なら前の変わり目から直線的に変える．後で呼ぶ beats と bars はこのテンポで数える
+fn tempo(context: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let mut args = args.into_iter();
+    let bpm = args.next().unwrap().unwrap();
+    let bpm = match number(&bpm)? {
+        x if x > 0. && x.is_finite() => x,
+        _ => return Err(Error::InvalidArgument(bpm.0, bpm.1.type_name())),
+    };
+    let at = match args.next().unwrap() {
+        Some(at) => match number(&at)? {
+            x if x >= 0. && x.is_finite() => Some(x),
+            _ => return Err(Error::InvalidArgument(at.0, at.1.type_name())),
+        },
+        None => None,
+    };
+    let ramp = match args.next().unwrap() {
+        Some((_, Value::Boolean(ramp))) => ramp,
+        Some((pos, value)) => return Err(Error::InvalidArgument(pos, value.type_name())),
+        None => false,
+    };
+    match at {
+        Some(at) => context.tempo.change(at, bpm, ramp),
+        None => context.tempo.set(bpm),
+    }
+    Ok(Value::Void)
+}
+
+// 1 小節の拍数を決める
+fn meter(context: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let beats = args.into_iter().flatten().next().unwrap();
+    match number(&beats)? {
+        x if x > 0. && x.is_finite() => context.tempo.set_meter(x),
+        _ => return Err(Error::InvalidArgument(beats.0, beats.1.type_name())),
+    }
+    Ok(Value::Void)
+}
+
+// from 拍目（省略したら 0）から beats 拍の秒数
+fn beats(context: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let mut args = args.into_iter();
+    let beats = number(&args.next().unwrap().unwrap())?;
+    let from = args.next().unwrap().map_or(Ok(0.), |from| number(&from))?;
+    let tempo = &context.tempo;
+    Ok(Value::Float(
+        tempo.seconds(from + beats) - tempo.seconds(from),
+    ))
+}
+
+// from 小節目（省略したら 0）から bars 小節の秒数
+fn bars(context: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let mut args = args.into_iter();
+    let bars = number(&args.next().unwrap().unwrap())?;
+    let from = args.next().unwrap().map_or(Ok(0.), |from| number(&from))?;
+    let tempo = &context.tempo;
+    let (from, to) = (from * tempo.meter(), (from + bars) * tempo.meter());
+    Ok(Value::Float(tempo.seconds(to) - tempo.seconds(from)))
+}
+
+// WAV ファイルに書き出す
+//
+// サンプリング周波数は書き出すときに決める（省略したら SAMPLE_RATE）
+fn write(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let mut args = args.into_iter();
+    let sound = match args.next().unwrap().unwrap() {
+        (_, Value::Sound(sound)) => sound,
+        (pos, value) => return Err(Error::InvalidArgument(pos, value.type_name())),
+    };
+    let (pos, path) = match args.next().unwrap().unwrap() {
+        (pos, Value::String(path)) => (pos, path),
+        (pos, value) => return Err(Error::InvalidArgument(pos, value.type_name())),
+    };
+    let cannot_write = |err: std::io::Error| {
+        Error::CannotWriteFile(pos.clone(), path.to_string(), err.to_string())
+    };
+    // 省略したら使えるだけのスレッドで計算する
+    let threads = match args.next().unwrap() {
+        Some((_, Value::Integer(n))) if n > 0 => n as usize,
+        Some((pos, value)) => return Err(Error::InvalidArgument(pos, value.type_name())),
+        None => std::thread::available_parallelism().map_or(1, NonZero::get),
+    };
+    // 1 秒あたりのバイト数が WAV のヘッダーに収まること
+    let channels = sound.channels().len() as u64;
+    let rate = match args.next().unwrap() {
+        Some((_, Value::Integer(n))) if n > 0 && n as u64 * channels * 2 <= u32::MAX as u64 => {
+            n as u32
+        }
+        Some((pos, value)) => return Err(Error::InvalidArgument(pos, value.type_name())),
+        None => sound::SAMPLE_RATE,
+    };
+    let file = std::fs::File::create(&*path).map_err(cannot_write)?;
+    let mut out = std::io::BufWriter::new(file);
+    sound
+        .write_wav(&mut out, threads, rate)
+        .map_err(cannot_write)?;
+    Ok(Value::Void)
+}
+
+// WAV ファイルを読む（16 ビットのリニア PCM のみ）
+fn read(_: &mut Context, _: &pos::Range, args: Vec<Option<PValue>>) -> Result<Value, Error> {
+    let (pos, path) = match args.into_iter().flatten().next().unwrap() {
+        (pos, Value::String(path)) => (pos, path),
+        (pos, value) => return Err(Error::InvalidArgument(pos, value.type_name())),
+    };
+    let sound = std::fs::File::open(&*path)
+        .map(std::io::BufReader::new)
+        .and_then(|mut input| Sound::read_wav(&mut input))
+        .map_err(|err| Error::CannotOpenFile(pos, path.to_string(), err.to_string()))?;
+    Ok(Value::Sound(Rc::new(sound)))
+}
+
+fn sound(arg: PValue) -> Result<(pos::Range, Rc<Sound>), Error> {
+    match arg {
+        (pos, Value::Sound(sound)) => Ok((pos, sound)),
+        (pos, value) => Err(Error::InvalidArgument(pos, value.type_name())),
+    }
+}
+
+// 音を times 回続けて並べる（多すぎるか，終わりの時刻が表せなければ pos の誤り）
+fn repetitions(pos: &pos::Range, sound: &Sound, times: i64) -> Result<Vec<Sound>, Error> {
+    if times > MAX_REPETITIONS {
+        return Err(Error::TooManyRepetitions(pos.clone(), times));
+    }
+    (0..times)
+        .map(|i| match sound.len().checked_mul(i) {
+            Some(delay) => Ok(sound.shift(delay)),
+            None => Err(Error::TooLongSound(pos.clone())),
+        })
+        .collect()
+}
+
+// 音を重ねる（チャンネル数が合わなければ，音のリストの位置の誤り）
+//
+// 長い列でもグラフが深くならないよう，隣どうしを重ねることを繰り返す
+fn mix(pos: &pos::Range, mut sounds: Vec<Sound>) -> Result<Sound, Error> {
+    if sounds.is_empty() {
+        return Err(Error::InvalidChannels(pos.clone(), 0));
+    }
+    while sounds.len() > 1 {
+        sounds = sounds
+            .chunks(2)
+            .map(|pair| match pair {
+                [l, r] => l.zip(r, |l, r| l.zip(Op::Add, r)).ok_or_else(|| {
+                    let channels = l.channels().len().max(r.channels().len());
+                    Error::InvalidChannels(pos.clone(), channels)
+                }),
+                [sound] => Ok(sound.clone()),
+                _ => unreachable!(),
+            })
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(sounds.pop().unwrap())
+}
+
+fn number((pos, value): &PValue) -> Result<f64, Error> {
+    match *value {
+        Value::Integer(n) => Ok(n.into()),
+        Value::Float(x) => Ok(x),
+        _ => Err(Error::InvalidArgument(pos.clone(), value.type_name())),
+    }
+}
+
+// モノラルの音の信号
+fn mono((pos, value): &PValue) -> Result<&Signal, Error> {
+    match value {
+        Value::Sound(sound) => match sound.channels() {
+            [signal] => Ok(signal),
+            channels => Err(Error::InvalidChannels(pos.clone(), channels.len())),
+        },
+        _ => Err(Error::InvalidArgument(pos.clone(), value.type_name())),
+    }
+}
+
+// 秒数で表した長さ（負なら 0）
+fn length(arg: &PValue) -> Result<i64, Error> {
+    Ok(sound::ticks(number(arg)?).max(0))
+}
@@ -0,0 +1,595 @@
+// 構文木をバイトコードに翻訳する
+//
+// 命令は小さく保ち，値や位置などは Code の表に置いて添字で指す
+
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use crate::expr::{self, BinOp, Expr, UnOp};
+use crate::pos;
+use crate::sentence::{self, Sentence};
+use crate::value::Value;
+
+mod test;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Instr {
+    // 定数を積む
+    Constant(u32),
+    // 変数（名前，位置）の値を積む
+    Load(u32, u32),
+    // 一番上の値を変数に代入する（値は残す）
+    Store(u32),
+    // 関数の仮引数を番号で読み書きする
+    LoadSlot(u32),
+    StoreSlot(u32),
+    // 値と添字を取り除き，代入先にたどって代入してから値を積む
+    StorePlace(u32),
+    // 添字を取り除き，代入先の今の値を積む
+    LoadPlace(u32),
+    Pop,
+    Dup,
+    // 上から n 個の値を複製して積む
+    DupN(u32),
+    Swap,
+    // 一番上の値を n 個下に移す
+    Rotate(u32),
+    // 演算子と被演算子の位置
+    UnOp(UnOp, u32, u32),
+    BinOp(BinOp, u32, u32, u32),
+    // 比較して，右辺と結果を積む
+    Compare(BinOp, u32, u32, u32),
+    // 一番上の値を真偽値にする（論理演算子の被演算子，条件）
+    LogicalOperand(u32, u32),
+    Condition(u32),
+    // 真偽値を取り除き，それによって飛ぶ
+    JumpIfFalse(u32),
+    JumpIfTrue(u32),
+    Jump(u32),
+    Call(u32),
+    // 要素数
+    List(u32),
+    Record(u32),
+    // 添字を付ける式と添字の位置
+    Index(u32, u32),
+    Member(u32),
+    EnterScope,
+    LeaveScope,
+    Import(u32),
+    Function(u32),
+    Return(u32),
+}
+
+// 関数呼び出しの位置と引数
+//
+// スタックには関数（`a.f(...)` なら a），位置による引数，名前付きの引数の順に積む
+pub struct Call {
+    pub pos: pos::Range,
+    pub fnc: pos::Range,
+    // `a.f(...)` なら a の位置と f
+    pub method: Option<(pos::Range, expr::PName)>,
+    pub args: Vec<pos::Range>,
+    pub named_args: Vec<(expr::PName, pos::Range)>,
+}
+
+// 代入先（変数とそこからたどる添字やフィールド）
+pub struct Place {
+    pub variable: expr::PName,
+    pub keys: Vec<PlaceKey>,
+}
+
+pub enum PlaceKey {
+    // 添字を付ける式と添字の位置
+    Index(pos::Range, pos::Range),
+    Member(expr::PName),
+}
+
+// 関数の本体と，仮引数の既定値を求めるコード
+pub struct Function {
+    pub definition: Rc<sentence::Function>,
+    // 仮引数の名前（呼ぶときの有効範囲で番号を振る）
+    pub params: Rc<[String]>,
+    pub defaults: Vec<Option<Code>>,
+    pub body: Code,
+}
+
+#[derive(Default)]
+pub struct Code {
+    pub instrs: Vec<Instr>,
+    pub constants: Vec<Value>,
+    pub names: Vec<String>,
+    pub ranges: Vec<pos::Range>,
+    pub calls: Vec<Call>,
+    pub places: Vec<Place>,
+    pub members: Vec<expr::PName>,
+    pub records: Vec<Vec<String>>,
+    // import の path の位置，path，束縛する名前
+    pub imports: Vec<(pos::Range, String, Option<expr::PName>)>,
+    pub functions: Vec<Rc<Function>>,
+    // 番号で読み書きする仮引数の名前（関数の本体を翻訳するときだけ）
+    slots: Vec<String>,
+}
+
+// 最上位の文
+//
+// 式の文なら，その値を残して終わる
+pub fn sentence(sentence: &sentence::PSentence) -> Code {
+    let mut code = Code::default();
+    match sentence {
+        (_, Sentence::Expr(Some(expr))) => code.expr(expr),
+        sentence => code.sentence(sentence),
+    }
+    code
+}
+
+impl Code {
+    fn emit(&mut self, instr: Instr) -> usize {
+        self.instrs.push(instr);
+        self.instrs.len() - 1
+    }
+    fn here(&self) -> u32 {
+        self.instrs.len() as u32
+    }
+    // 飛び先を今の位置にする
+    fn patch(&mut self, at: usize) {
+        let here = self.here();
+        match &mut self.instrs[at] {
+            Instr::Jump(target) | Instr::JumpIfFalse(target) | Instr::JumpIfTrue(target) => {
+                *target = here
+            }
+            _ => unreachable!(),
+        }
+    }
+    fn add<T>(table: &mut Vec<T>, item: T) -> u32 {
+        table.push(item);
+        table.len() as u32 - 1
+    }
+    fn range(&mut self, range: &pos::Range) -> u32 {
+        Code::add(&mut self.ranges, range.clone())
+    }
+    fn name(&mut self, name: &str) -> u32 {
+        match self.names.iter().position(|prev| prev == name) {
+            Some(index) => index as u32,
+            None => Code::add(&mut self.names, name.to_owned()),
+        }
+    }
+    fn slot(&self, name: &str) -> Option<u32> {
+        let index = self.slots.iter().position(|slot| slot == name)?;
+        Some(index as u32)
+    }
+    fn constant(&mut self, value: Value) {
+        let index = Code::add(&mut self.constants, value);
+        self.emit(Instr::Constant(index));
+    }
+
+    // 値を残さない文
+    fn sentence(&mut self, (pos, sentence): &sentence::PSentence) {
+        match sentence {
+            Sentence::Expr(Some(expr)) => {
+                self.expr(expr);
+                self.emit(Instr::Pop);
+            }
+            Sentence::Expr(None) => {}
+            Sentence::Import(pos, path, name) => {
+                let import = (pos.clone(), path.clone(), name.clone());
+                let index = Code::add(&mut self.imports, import);
+                self.emit(Instr::Import(index));
+            }
+            Sentence::Block(sentences) => {
+                self.emit(Instr::EnterScope);
+                sentences
+                    .iter()
+                    .for_each(|sentence| self.sentence(sentence));
+                self.emit(Instr::LeaveScope);
+            }
+            Sentence::Return(expr) => {
+                match expr {
+                    Some(expr) => self.expr(expr),
+                    None => self.constant(Value::Void),
+                }
+                let pos = self.range(pos);
+                self.emit(Instr::Return(pos));
+            }
+            Sentence::Function(definition) => {
+                let defaults = definition
+                    .params
+                    .iter()
+                    .map(|param| {
+                        param.default.as_ref().map(|default| {
+                            let mut code = Code::default();
+                            code.expr(default);
+                            code
+                        })
+                    })
+                    .collect();
+                let params: Rc<[String]> = definition
+                    .params
+                    .iter()
+                    .map(|param| param.name.1.clone())
+                    .collect();
+                let mut body = Code::default();
+                // 内側のブロックで仮引数と同じ名前の関数を定義すると隠れるので，番号では読み書きしない
+                let mut shadowed = Vec::new();
+                definition
+                    .body
+                    .iter()
+                    .for_each(|sentence| block_functions(sentence, false, &mut shadowed));
+                if !params
+                    .iter()
+                    .any(|param| shadowed.contains(&param.as_str()))
+                {
+                    body.slots = params.to_vec();
+                }
+                definition
+                    .body
+                    .iter()
+                    .for_each(|sentence| body.sentence(sentence));
+                let function = Function {
+                    definition: definition.clone(),
+                    params,
+                    defaults,
+                    body,
+                };
+                let index = Code::add(&mut self.functions, function.into());
+                self.emit(Instr::Function(index));
+            }
+        }
+    }
+
+    // 値を 1 つ積む式
+    fn expr(&mut self, (pos, expr): &expr::PExpr) {
+        match expr {
+            Expr::Identifier(name) => match self.slot(name) {
+                Some(index) => {
+                    self.emit(Instr::LoadSlot(index));
+                }
+                None => {
+                    let (name, pos) = (self.name(name), self.range(pos));
+                    self.emit(Instr::Load(name, pos));
+                }
+            },
+            &Expr::Integer(n) => self.constant(Value::Integer(n)),
+            &Expr::Float(x) => self.constant(Value::Float(x)),
+            &Expr::Boolean(value) => self.constant(Value::Boolean(value)),
+            Expr::String(s) => self.constant(Value::String(s.as_str().into())),
+            Expr::UnOp(op, operand) => {
+                let update = matches!(
+                    op.1,
+                    UnOp::PreIncrement
+                        | UnOp::PreDecrement
+                        | UnOp::PostIncrement
+                        | UnOp::PostDecrement
+                );
+                let place = if update {
+                    self.load_place(operand)
+                } else {
+                    self.expr(operand);
+                    None
+                };
+                let post = matches!(op.1, UnOp::PostIncrement | UnOp::PostDecrement);
+                if post {
+                    // 元の値は添字より下に残す
+                    self.emit(Instr::Dup);
+                    if let Some((_, indices @ 1..)) = place {
+                        self.emit(Instr::Rotate(indices + 1));
+                    }
+                }
+                let (op_pos, operand_pos) = (self.range(&op.0), self.range(&operand.0));
+                self.emit(Instr::UnOp(op.1, op_pos, operand_pos));
+                if update {
+                    self.store_place(operand, place);
+                }
+                if post {
+                    self.emit(Instr::Pop);
+                }
+            }
+            Expr::BinOp(op, left, right) => match op.1 {
+                BinOp::Assign => {
+                    self.expr(right);
+                    self.store(left);
+                }
+                BinOp::LogicalAnd | BinOp::LogicalOr => {
+                    // 左辺で結果が決まれば右辺は評価しない
+                    let op_pos = self.range(&op.0);
+                    self.expr(left);
+                    let left_pos = self.range(&left.0);
+                    self.emit(Instr::LogicalOperand(op_pos, left_pos));
+                    self.emit(Instr::Dup);
+                    let jump = self.emit(match op.1 {
+                        BinOp::LogicalAnd => Instr::JumpIfFalse(0),
+                        _ => Instr::JumpIfTrue(0),
+                    });
+                    self.emit(Instr::Pop);
+                    self.expr(right);
+                    let right_pos = self.range(&right.0);
+                    self.emit(Instr::LogicalOperand(op_pos, right_pos));
+                    self.patch(jump);
+                }
+                _ => {
+                    let place = match op.1.compound() {
+                        Some(_) => self.load_place(left),
+                        None => {
+                            self.expr(left);
+                            None
+                        }
+                    };
+                    self.expr(right);
+                    let op_pos = self.range(&op.0);
+                    let (left_pos, right_pos) = (self.range(&left.0), self.range(&right.0));
+                    let bin_op = op.1.compound().unwrap_or(op.1);
+                    self.emit(Instr::BinOp(bin_op, op_pos, left_pos, right_pos));
+                    if op.1.compound().is_some() {
+                        self.store_place(left, place);
+                    }
+                }
+            },
+            Expr::Group(inner) => self.expr(inner),
+            Expr::Conditional(cond, then, otherwise) => {
+                self.expr(cond);
+                let cond_pos = self.range(&cond.0);
+                self.emit(Instr::Condition(cond_pos));
+                let jump_otherwise = self.emit(Instr::JumpIfFalse(0));
+                self.expr(then);
+                let jump_end = self.emit(Instr::Jump(0));
+                self.patch(jump_otherwise);
+                self.expr(otherwise);
+                self.patch(jump_end);
+            }
+            Expr::Comparison(first, rest) => {
+                // 偽になった比較より後の被演算子は評価しない
+                self.expr(first);
+                let mut left_pos = self.range(&first.0);
+                let mut jumps = Vec::new();
+                for (i, (op, right)) in rest.iter().enumerate() {
+                    self.expr(right);
+                    let (op_pos, right_pos) = (self.range(&op.0), self.range(&right.0));
+                    self.emit(Instr::Compare(op.1, op_pos, left_pos, right_pos));
+                    if i + 1 < rest.len() {
+                        jumps.push(self.emit(Instr::JumpIfFalse(0)));
+                    }
+                    left_pos = right_pos;
+                }
+                // 右辺を捨てて結果を残す
+                self.emit(Instr::Swap);
+                self.emit(Instr::Pop);
+                if !jumps.is_empty() {
+                    let jump_end = self.emit(Instr::Jump(0));
+                    jumps.into_iter().for_each(|jump| self.patch(jump));
+                    self.emit(Instr::Pop);
+                    self.constant(Value::Boolean(false));
+                    self.patch(jump_end);
+                }
+            }
+            Expr::Call(fnc, args, named_args) => {
+                let method = match &fnc.1 {
+                    Expr::Member(object, name) => {
+                        self.expr(object);
+                        Some((object.0.clone(), name.clone()))
+                    }
+                    _ => {
+                        self.expr(fnc);
+                        None
+                    }
+                };
+                args.iter().for_each(|arg| self.expr(arg));
+                named_args.iter().for_each(|(_, arg)| self.expr(arg));
+                let call = Call {
+                    pos: pos.clone(),
+                    fnc: fnc.0.clone(),
+                    method,
+                    args: args.iter().map(|(pos, _)| pos.clone()).collect(),
+                    named_args: named_args
+                        .iter()
+                        .map(|(name, (pos, _))| (name.clone(), pos.clone()))
+                        .collect(),
+                };
+                let index = Code::add(&mut self.calls, call);
+                self.emit(Instr::Call(index));
+            }
+            Expr::List(elems) => {
+                elems.iter().for_each(|elem| self.expr(elem));
+                self.emit(Instr::List(elems.len() as u32));
+            }
+            Expr::Index(container, index) => {
+                self.expr(container);
+                self.expr(index);
+                let (container_pos, index_pos) = (self.range(&container.0), self.range(&index.0));
+                self.emit(Instr::Index(container_pos, index_pos));
+            }
+            Expr::Member(object, name) => {
+                self.expr(object);
+                let index = Code::add(&mut self.members, name.clone());
+                self.emit(Instr::Member(index));
+            }
+            Expr::Record(fields) => {
+                fields.iter().for_each(|(_, value)| self.expr(value));
+                let names = fields.iter().map(|((_, name), _)| name.clone()).collect();
+                let index = Code::add(&mut self.records, names);
+                self.emit(Instr::Record(index));
+            }
+        }
+    }
+
+    // 一番上の値を代入する（値は残す）
+    fn store(&mut self, target: &expr::PExpr) {
+        if let (_, Expr::Identifier(name)) = target {
+            let instr = match self.slot(name) {
+                Some(index) => Instr::StoreSlot(index),
+                None => Instr::Store(self.name(name)),
+            };
+            self.emit(instr);
+            return;
+        }
+        let mut keys = Vec::new();
+        let variable = self.place(target, &mut keys);
+        let index = Code::add(&mut self.places, Place { variable, keys });
+        self.emit(Instr::StorePlace(index));
+    }
+
+    // 値を読んで書き換える代入先の今の値を積む
+    //
+    // 添字は 1 回だけ評価して複製し，代入するときのために下に残す．
+    // 変数だけなら None
+    fn load_place(&mut self, target: &expr::PExpr) -> Option<(u32, u32)> {
+        if let (_, Expr::Identifier(_)) = target {
+            self.expr(target);
+            return None;
+        }
+        let mut keys = Vec::new();
+        let variable = self.place(target, &mut keys);
+        let place = Place { variable, keys };
+        let indices = place.indices() as u32;
+        let index = Code::add(&mut self.places, place);
+        if indices > 0 {
+            self.emit(Instr::DupN(indices));
+        }
+        self.emit(Instr::LoadPlace(index));
+        Some((index, indices))
+    }
+
+    // load_place で積んだ添字の上の値を代入する（値は残す）
+    fn store_place(&mut self, target: &expr::PExpr, place: Option<(u32, u32)>) {
+        match place {
+            Some((index, indices)) => {
+                if indices > 0 {
+                    self.emit(Instr::Rotate(indices));
+                }
+                self.emit(Instr::StorePlace(index));
+            }
+            None => self.store(target),
+        }
+    }
+
+    // 添字は左から順に評価する
+    fn place(&mut self, (pos, target): &expr::PExpr, keys: &mut Vec<PlaceKey>) -> expr::PName {
+        match target {
+            Expr::Identifier(name) => (pos.clone(), name.clone()),
+            Expr::Index(container, index) => {
+                let variable = self.place(container, keys);
+                self.expr(index);
+                keys.push(PlaceKey::Index(container.0.clone(), index.0.clone()));
+                variable
+            }
+            Expr::Member(object, name) => {
+                let variable = self.place(object, keys);
+                keys.push(PlaceKey::Member(name.clone()));
+                variable
+            }
+            // check::sentence で確かめてある
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Place {
+    // スタックに積む添字の数
+    pub fn indices(&self) -> usize {
+        self.keys
+            .iter()
+            .filter(|key| matches!(key, PlaceKey::Index(..)))
+            .count()
+    }
+}
+
+impl Display for Place {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.variable.1)?;
+        for key in &self.keys {
+            match key {
+                PlaceKey::Index(..) => write!(f, "[]")?,
+                PlaceKey::Member((_, name)) => write!(f, ".{name}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// ブロックの中で定義する関数の名前を集める（内側の関数の本体には入らない）
+fn block_functions<'a>(
+    (_, sentence): &'a sentence::PSentence,
+    in_block: bool,
+    names: &mut Vec<&'a str>,
+) {
+    match sentence {
+        Sentence::Block(sentences) => sentences
+            .iter()
+            .for_each(|sentence| block_functions(sentence, true, names)),
+        Sentence::Function(definition) if in_block => names.push(&definition.name.1),
+        _ => {}
+    }
+}
+
+// 逆アセンブル
+impl Display for Code {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (i, instr) in self.instrs.iter().enumerate() {
+            write!(f, "{i:04} ")?;
+            match *instr {
+                Instr::Constant(index) => match &self.constants[index as usize] {
+                    Value::String(s) => writeln!(f, "Constant {s:?}"),
+                    value => writeln!(f, "Constant {value}"),
+                },
+                Instr::Load(name, _) => writeln!(f, "Load {}", self.names[name as usize]),
+                Instr::Store(name) => writeln!(f, "Store {}", self.names[name as usize]),
+                Instr::LoadSlot(index) => writeln!(f, "LoadSlot {index}"),
+                Instr::StoreSlot(index) => writeln!(f, "StoreSlot {index}"),
+                Instr::StorePlace(index) => {
+                    writeln!(f, "StorePlace {}", self.places[index as usize])
+                }
+                Instr::LoadPlace(index) => writeln!(f, "LoadPlace {}", self.places[index as usize]),
+                Instr::UnOp(op, ..) => writeln!(f, "UnOp {op:?}"),
+                Instr::BinOp(op, ..) => writeln!(f, "BinOp {op:?}"),
+                Instr::Compare(op, ..) => writeln!(f, "Compare {op:?}"),
+                Instr::JumpIfFalse(target) => writeln!(f, "JumpIfFalse {target:04}"),
+                Instr::JumpIfTrue(target) => writeln!(f, "JumpIfTrue {target:04}"),
+                Instr::Jump(target) => writeln!(f, "Jump {target:04}"),
+                Instr::Call(index) => {
+                    let call = &self.calls[index as usize];
+                    write!(f, "Call")?;
+                    if let Some((_, (_, name))) = &call.method {
+                        write!(f, " .{name}")?;
+                    }
+                    write!(f, " {}", call.args.len())?;
+                    for ((_, name), _) in &call.named_args {
+                        write!(f, " {name}:")?;
+                    }
+                    writeln!(f)
+                }
+                Instr::List(len) => writeln!(f, "List {len}"),
+                Instr::Record(index) => {
+                    writeln!(f, "Record {}", self.records[index as usize].join(" "))
+                }
+                Instr::Member(index) => writeln!(f, "Member {}", self.members[index as usize].1),
+                Instr::Import(index) => match &self.imports[index as usize] {
+                    (_, path, Some((_, name))) => writeln!(f, "Import {path:?} as {name}"),
+                    (_, path, None) => writeln!(f, "Import {path:?}"),
+                },
+                Instr::Function(index) => {
+                    let function = &self.functions[index as usize];
+                    writeln!(f, "Function {}", function.definition.name.1)
+                }
+                Instr::Pop => writeln!(f, "Pop"),
+                Instr::Dup => writeln!(f, "Dup"),
+                Instr::DupN(n) => writeln!(f, "DupN {n}"),
+                Instr::Swap => writeln!(f, "Swap"),
+                Instr::Rotate(n) => writeln!(f, "Rotate {n}"),
+                Instr::LogicalOperand(..) => writeln!(f, "LogicalOperand"),
+                Instr::Condition(_) => writeln!(f, "Condition"),
+                Instr::Index(..) => writeln!(f, "Index"),
+                Instr::EnterScope => writeln!(f, "EnterScope"),
+                Instr::LeaveScope => writeln!(f, "LeaveScope"),
+                Instr::Return(_) => writeln!(f, "Return"),
+            }?;
+        }
+        for function in &self.functions {
+            writeln!(f, "\n<function {}>", function.definition.name.1)?;
+            for (param, default) in function.definition.params.iter().zip(&function.defaults) {
+                if let Some(default) = default {
+                    writeln!(f, "<default {}>\n{default}", param.name.1)?;
+                }
+            }
+            write!(f, "{}", function.body)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+#![cfg(test)]
+
+use crate::lexer::Lexer;
+use crate::parser;
+
+fn disasm(input: &str) -> String {
+    let mut lexer = Lexer::from_str("test", input);
+    let (pos, sentence) = parser::parse_sentence(&mut lexer).ok().flatten().unwrap();
+    super::sentence(&(pos, sentence.into())).to_string()
+}
+
+#[test]
+fn expression() {
+    assert_eq!(
+        disasm("x = a && f(1, b: 2);"),
+        "\
+0000 Load a
+0001 LogicalOperand
+0002 Dup
+0003 JumpIfFalse 0010
+0004 Pop
+0005 Load f
+0006 Constant 1
+0007 Constant 2
+0008 Call 1 b:
+0009 LogicalOperand
+0010 Store x
+"
+    );
+}
+
+#[test]
+fn assignment() {
+    assert_eq!(
+        disasm("p.xs[i]++;"),
+        "\
+0000 Load i
+0001 DupN 1
+0002 LoadPlace p.xs[]
+0003 Dup
+0004 Rotate 2
+0005 UnOp PostIncrement
+0006 Rotate 1
+0007 StorePlace p.xs[]
+0008 Pop
+"
+    );
+    // 添字は 1 回だけ評価して複製する
+    assert_eq!(
+        disasm("xs[i] += 1;"),
+        "\
+0000 Load i
+0001 DupN 1
+0002 LoadPlace xs[]
+0003 Constant 1
+0004 BinOp Add
+0005 Rotate 1
+0006 StorePlace xs[]
+"
+    );
+}
+
+#[test]
+fn function() {
+    assert_eq!(
+        disasm("f(x: 1) { return x; }"),
+        "\
+0000 Function f
+
+<function f>
+<default x>
+0000 Constant 1
+
+0000 LoadSlot 0
+0001 Return
+"
+    );
+}
@@ -0,0 +1,622 @@
+mod builtin;
+pub mod compile;
+pub mod operator;
+mod scope;
+pub use builtin::Context;
+pub use scope::Scope;
+mod vm;
+
+mod test;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::check;
+use crate::error::Error;
+use crate::expr::{self, BinOp, Expr, UnOp};
+use crate::fold;
+use crate::lexer::{self, Lexer};
+use crate::parser;
+use crate::pos;
+use crate::sentence::{self, Sentence};
+use crate::value::{self, Module, PValue, Value};
+
+// 関数呼び出しの入れ子の深さの上限
+//
+// これに達する前にスタックが溢れないよう，STACK_SIZE のスタックを持つスレッドで実行する
+const MAX_DEPTH: usize = 1000;
+pub const STACK_SIZE: usize = 256 << 20;
+
+// 文を実行した後にすること
+enum Flow {
+    // 次の文に進む（式の文ならその値を持つ）
+    Next(Value),
+    // 関数から戻る
+    Return(pos::Range, Value),
+}
+
+// 文を実行する方法
+#[derive(Clone, Copy)]
+pub enum Backend {
+    // 構文木をたどる
+    Tree,
+    // バイトコードに翻訳して実行する
+    Vm,
+}
+
+pub struct Interpreter {
+    backend: Backend,
+    depth: usize,
+    // 読み込み済みのモジュール（正規化したパスごと）
+    modules: HashMap<PathBuf, Rc<Module>>,
+    // 読み込んでいる途中のファイル（循環の検出に使う）
+    importing: Vec<PathBuf>,
+    // 組み込み関数が読み書きする状態
+    context: Context,
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter {
+            backend: Backend::Vm,
+            depth: 0,
+            modules: HashMap::new(),
+            importing: Vec::new(),
+            context: Context::new(),
+        }
+    }
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    // ファイルを最後まで実行し，その名前空間をモジュールとして返す
+    pub fn run_file(&mut self, mut lexer: Lexer, path: &Path) -> Result<Rc<Module>, Error> {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let name = path
+            .file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+        let module = Rc::new(Module {
+            name,
+            scope: Scope::new(None),
+        });
+        self.importing.push(path.clone());
+        let result = self.run(&mut lexer, &module.scope);
+        self.importing.pop();
+        result?;
+        self.modules.insert(path, module.clone());
+        Ok(module)
+    }
+
+    // 最後まで読んですべての文を検査してから，順に実行する
+    pub fn run(&mut self, lexer: &mut Lexer, scope: &Rc<Scope>) -> Result<(), Error> {
+        let mut sentences = Vec::new();
+        while let Some((pos, sentence)) = parser::parse_sentence(lexer)? {
+            let sentence = (pos, sentence.into());
+            check::sentence(&sentence)?;
+            sentences.push(sentence);
+        }
+        for sentence in sentences {
+            self.exec_checked(sentence, scope)?;
+        }
+        Ok(())
+    }
+
+    // 1 つの文を検査して実行し，式の文ならその値を返す（対話環境で使う）
+    pub fn exec(
+        &mut self,
+        sentence: sentence::PSentence,
+        scope: &Rc<Scope>,
+    ) -> Result<Value, Error> {
+        check::sentence(&sentence)?;
+        self.exec_checked(sentence, scope)
+    }
+
+    fn exec_checked(
+        &mut self,
+        sentence: sentence::PSentence,
+        scope: &Rc<Scope>,
+    ) -> Result<Value, Error> {
+        let sentence = fold::sentence(sentence);
+        let flow = match self.backend {
+            Backend::Tree => self.exec_sentence(&sentence, scope)?,
+            Backend::Vm => self.run_code(&compile::sentence(&sentence), scope)?,
+        };
+        match flow {
+            Flow::Next(value) => Ok(value),
+            Flow::Return(pos, _) => Err(Error::ReturnOutsideFunction(pos)),
+        }
+    }
+
+    fn exec_sentence(
+        &mut self,
+        (pos, sentence): &sentence::PSentence,
+        scope: &Rc<Scope>,
+    ) -> Result<Flow, Error> {
+        match sentence {
+            Sentence::Expr(Some(expr)) => Ok(Flow::Next(self.eval(expr, scope)?)),
+            Sentence::Expr(None) => Ok(Flow::Next(Value::Void)),
+            Sentence::Import(pos, path, name) => {
+                self.import_as(pos, path, name.as_ref(), scope)?;
+                Ok(Flow::Next(Value::Void))
+            }
+            Sentence::Block(sentences) => {
+                self.exec_block(sentences, &Scope::new(Some(scope.clone())))
+            }
+            Sentence::Return(expr) => {
+                let value = match expr {
+                    Some(expr) => self.eval(expr, scope)?,
+                    None => Value::Void,
+                };
+                Ok(Flow::Return(pos.clone(), value))
+            }
+            Sentence::Function(definition) => {
+                let function = value::Function {
+                    definition: definition.clone(),
+                    scope: scope.clone(),
+                    code: None,
+                };
+                scope.define(&definition.name.1, Value::Function(function.into()));
+                Ok(Flow::Next(Value::Void))
+            }
+        }
+    }
+
+    fn exec_block(
+        &mut self,
+        sentences: &[sentence::PSentence],
+        scope: &Rc<Scope>,
+    ) -> Result<Flow, Error> {
+        for sentence in sentences {
+            if let flow @ Flow::Return(..) = self.exec_sentence(sentence, scope)? {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Next(Value::Void))
+    }
+
+    fn call(
+        &mut self,
+        pos: &pos::Range,
+        function: &value::Function,
+        args: Vec<PValue>,
+        named_args: Vec<(expr::PName, PValue)>,
+    ) -> Result<Value, Error> {
+        let params = &function.definition.params;
+        let names = params
+            .iter()
+            .map(|param| (param.name.1.as_str(), param.default.is_some()));
+        let args = bind_args(pos, names, args, named_args)?;
+        let args = args.into_iter().map(|arg| arg.map(|(_, value)| value));
+        self.enter(pos, |interpreter| interpreter.call_body(function, args))
+    }
+
+    // 位置による引数だけを渡すときは，引数を揃えずに呼ぶ（足りない分は既定値にする）
+    pub(super) fn call_values(
+        &mut self,
+        pos: &pos::Range,
+        function: &value::Function,
+        values: Vec<Value>,
+    ) -> Result<Value, Error> {
+        let args = values.into_iter().map(Some).chain(std::iter::repeat(None));
+        self.enter(pos, |interpreter| interpreter.call_body(function, args))
+    }
+
+    // 再帰の深さを数えながら実行する
+    fn enter(
+        &mut self,
+        pos: &pos::Range,
+        f: impl FnOnce(&mut Interpreter) -> Result<Value, Error>,
+    ) -> Result<Value, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::TooDeepRecursion(pos.clone()));
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn call_body(
+        &mut self,
+        function: &value::Function,
+        args: impl Iterator<Item = Option<Value>>,
+    ) -> Result<Value, Error> {
+        let scope = match &function.code {
+            Some(code) => Scope::with_slots(function.scope.clone(), code.params.clone()),
+            None => Scope::new(Some(function.scope.clone())),
+        };
+        let params = function.definition.params.iter().enumerate();
+        for ((i, param), arg) in params.zip(args) {
+            let value = match (arg, &param.default, &function.code) {
+                (Some(value), _, _) => value,
+                (None, Some(default), None) => self.eval(default, &scope)?,
+                (None, Some(_), Some(code)) => {
+                    match self.run_code(code.defaults[i].as_ref().unwrap(), &scope)? {
+                        Flow::Next(value) => value,
+                        Flow::Return(..) => unreachable!(),
+                    }
+                }
+                (None, None, _) => unreachable!(),
+            };
+            scope.define(&param.name.1, value);
+        }
+        let flow = match &function.code {
+            Some(code) => self.run_code(&code.body, &scope)?,
+            None => self.exec_block(&function.definition.body, &scope)?,
+        };
+        match flow {
+            Flow::Return(_, value) => Ok(value),
+            Flow::Next(_) => Ok(Value::Void),
+        }
+    }
+
+    // モジュールを読み込んで name に束縛する
+    //
+    // name を省略するとモジュールの名前とし，それが識別子でなければ誤り．
+    // 別のモジュールが束縛されている名前にも束縛しない
+    fn import_as(
+        &mut self,
+        pos: &pos::Range,
+        path: &str,
+        name: Option<&expr::PName>,
+        scope: &Rc<Scope>,
+    ) -> Result<(), Error> {
+        let module = self.import(pos, path)?;
+        let (pos, name) = match name {
+            Some((pos, name)) => (pos, name),
+            None if lexer::is_identifier(&module.name) => (pos, &module.name),
+            None => return Err(Error::InvalidModuleName(pos.clone(), module.name.clone())),
+        };
+        if let Some(Value::Module(bound)) = lookup(name, scope) {
+            if !Rc::ptr_eq(&bound, &module) {
+                return Err(Error::ModuleNameConflict(pos.clone(), name.clone()));
+            }
+        }
+        scope.assign(name, Value::Module(module.clone()));
+        Ok(())
+    }
+
+    fn import(&mut self, pos: &pos::Range, path: &str) -> Result<Rc<Module>, Error> {
+        // 相対パスは import を書いたファイルのあるディレクトリから探す
+        // （標準入力などファイルでないときはカレントディレクトリから）
+        let dir = Path::new(pos.source().name()).parent();
+        let path = dir.unwrap_or(Path::new("")).join(path);
+        let cannot_open = |err: std::io::Error| {
+            Error::CannotOpenFile(pos.clone(), path.display().to_string(), err.to_string())
+        };
+        let canonical = std::fs::canonicalize(&path).map_err(cannot_open)?;
+        if let Some(module) = self.modules.get(&canonical) {
+            return Ok(module.clone());
+        }
+        if let Some(index) = self.importing.iter().position(|file| *file == canonical) {
+            let cycle = self.importing[index..]
+                .iter()
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(Error::ImportCycle(pos.clone(), cycle));
+        }
+        let lexer = Lexer::from_path(&path).map_err(cannot_open)?;
+        self.run_file(lexer, &path)
+            .map_err(|err| Error::InImportedFile(pos.clone(), err.into()))
+    }
+
+    fn eval(&mut self, (pos, expr): &expr::PExpr, scope: &Rc<Scope>) -> Result<Value, Error> {
+        match expr {
+            Expr::Identifier(name) => {
+                lookup(name, scope).ok_or_else(|| Error::UndefinedVariable(pos.clone()))
+            }
+            &Expr::Integer(n) => Ok(Value::Integer(n)),
+            &Expr::Float(x) => Ok(Value::Float(x)),
+            &Expr::Boolean(value) => Ok(Value::Boolean(value)),
+            Expr::String(s) => Ok(Value::String(s.as_str().into())),
+            Expr::UnOp(op, operand) => match op.1 {
+                UnOp::PreIncrement
+                | UnOp::PreDecrement
+                | UnOp::PostIncrement
+                | UnOp::PostDecrement => {
+                    // 代入先の添字は 1 回だけ評価する
+                    let mut path = Vec::new();
+                    let variable = self.place(operand, &mut path, scope)?;
+                    let value = load(scope, variable, &path)?;
+                    let result = operator::unary((&op.0, op.1), (&operand.0, value.clone()))?;
+                    let result = store(scope, variable, path, result)?;
+                    match op.1 {
+                        UnOp::PostIncrement | UnOp::PostDecrement => Ok(value),
+                        _ => Ok(result),
+                    }
+                }
+                _ => {
+                    let value = self.eval(operand, scope)?;
+                    operator::unary((&op.0, op.1), (&operand.0, value))
+                }
+            },
+            Expr::BinOp(op, left, right) => match op.1 {
+                BinOp::Assign => {
+                    let value = self.eval(right, scope)?;
+                    self.assign(left, value, scope)
+                }
+                BinOp::LogicalAnd | BinOp::LogicalOr => {
+                    // 左辺で結果が決まれば右辺は評価しない
+                    let truth = |expr: &expr::PExpr, value: Value| {
+                        operator::truth(&value).ok_or_else(|| {
+                            Error::InvalidOperand(op.0.clone(), expr.0.clone(), value.type_name())
+                        })
+                    };
+                    let left_value = self.eval(left, scope)?;
+                    let left_truth = truth(left, left_value)?;
+                    if left_truth == (op.1 == BinOp::LogicalOr) {
+                        return Ok(Value::Boolean(left_truth));
+                    }
+                    let right_value = self.eval(right, scope)?;
+                    Ok(Value::Boolean(truth(right, right_value)?))
+                }
+                _ => match op.1.compound() {
+                    Some(bin_op) => {
+                        // 代入先の添字は 1 回だけ評価し，同じ所を読んで書く
+                        let mut path = Vec::new();
+                        let variable = self.place(left, &mut path, scope)?;
+                        let left_value = load(scope, variable, &path)?;
+                        let right_value = self.eval(right, scope)?;
+                        let op = (&op.0, bin_op);
+                        let value =
+                            operator::binary(op, (&left.0, left_value), (&right.0, right_value))?;
+                        store(scope, variable, path, value)
+                    }
+                    None => {
+                        let left_value = self.eval(left, scope)?;
+                        let right_value = self.eval(right, scope)?;
+                        operator::binary(
+                            (&op.0, op.1),
+                            (&left.0, left_value),
+                            (&right.0, right_value),
+                        )
+                    }
+                },
+            },
+            Expr::Group(inner) => self.eval(inner, scope),
+            Expr::Conditional(cond, then, otherwise) => {
+                let value = self.eval(cond, scope)?;
+                let truth = operator::truth(&value)
+                    .ok_or_else(|| Error::InvalidCondition(cond.0.clone(), value.type_name()))?;
+                self.eval(if truth { then } else { otherwise }, scope)
+            }
+            Expr::Comparison(first, rest) => {
+                // 偽になった比較より後の被演算子は評価しない
+                let mut left = (&first.0, self.eval(first, scope)?);
+                for (op, right) in rest {
+                    let right_value = self.eval(right, scope)?;
+                    let result =
+                        operator::binary((&op.0, op.1), left, (&right.0, right_value.clone()))?;
+                    if let Value::Boolean(false) = result {
+                        return Ok(result);
+                    }
+                    left = (&right.0, right_value);
+                }
+                Ok(Value::Boolean(true))
+            }
+            Expr::Call(fnc, args, named_args) => {
+                let (fnc_value, receiver) = match &fnc.1 {
+                    Expr::Member(object, name) => {
+                        let object_value = self.eval(object, scope)?;
+                        method((&object.0, object_value), name, scope)?
+                    }
+                    _ => (self.eval(fnc, scope)?, None),
+                };
+                let args = receiver
+                    .into_iter()
+                    .map(Ok)
+                    .chain(
+                        args.iter()
+                            .map(|arg| Ok((arg.0.clone(), self.eval(arg, scope)?))),
+                    )
+                    .collect::<Result<Vec<_>, Error>>()?;
+                let named_args = named_args
+                    .iter()
+                    .map(|(name, arg)| Ok((name.clone(), (arg.0.clone(), self.eval(arg, scope)?))))
+                    .collect::<Result<Vec<_>, Error>>()?;
+                self.call_value(pos, (&fnc.0, fnc_value), args, named_args)
+            }
+            Expr::List(elems) => {
+                let elems = elems
+                    .iter()
+                    .map(|elem| self.eval(elem, scope))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::List(Rc::new(elems)))
+            }
+            Expr::Index(container, index) => {
+                let container_value = self.eval(container, scope)?;
+                let index_value = self.eval(index, scope)?;
+                operator::index((&container.0, container_value), (&index.0, index_value))
+            }
+            Expr::Member(object, name) => {
+                let object_value = self.eval(object, scope)?;
+                operator::member(object_value, name)
+            }
+            Expr::Record(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|((_, name), value)| Ok((name.clone(), self.eval(value, scope)?)))
+                    .collect::<Result<_, Error>>()?;
+                Ok(Value::Record(Rc::new(value::Record { fields })))
+            }
+        }
+    }
+
+    // 関数や組み込み関数を呼ぶ
+    fn call_value(
+        &mut self,
+        pos: &pos::Range,
+        (fnc_pos, fnc): (&pos::Range, Value),
+        args: Vec<PValue>,
+        named_args: Vec<(expr::PName, PValue)>,
+    ) -> Result<Value, Error> {
+        match fnc {
+            Value::Builtin(builtin) => {
+                let args = match builtin.params {
+                    Some(params) => bind_args(pos, params.iter().copied(), args, named_args)?,
+                    None => match named_args.first() {
+                        Some(((pos, _), _)) => return Err(Error::UnknownArgument(pos.clone())),
+                        None => args.into_iter().map(Some).collect(),
+                    },
+                };
+                (builtin.fnc)(&mut self.context, pos, args)
+            }
+            Value::Function(function) => self.call(pos, &function, args, named_args),
+            _ => Err(Error::NotCallable(fnc_pos.clone(), fnc.type_name())),
+        }
+    }
+
+    fn assign(
+        &mut self,
+        target: &expr::PExpr,
+        value: Value,
+        scope: &Rc<Scope>,
+    ) -> Result<Value, Error> {
+        let mut path = Vec::new();
+        let variable = self.place(target, &mut path, scope)?;
+        store(scope, variable, path, value)
+    }
+
+    // 代入先を，変数とそこからたどる添字やフィールドの並びに分ける
+    //
+    // 添字は左から順に評価する
+    fn place<'a>(
+        &mut self,
+        (pos, target): &'a expr::PExpr,
+        path: &mut Vec<Key<'a>>,
+        scope: &Rc<Scope>,
+    ) -> Result<(&'a pos::Range, &'a str), Error> {
+        match target {
+            Expr::Identifier(name) => Ok((pos, name)),
+            Expr::Index(container, index) => {
+                let variable = self.place(container, path, scope)?;
+                let index_value = self.eval(index, scope)?;
+                path.push(Key::Index(&container.0, (&index.0, index_value)));
+                Ok(variable)
+            }
+            Expr::Member(object, name) => {
+                let variable = self.place(object, path, scope)?;
+                path.push(Key::Member(name));
+                Ok(variable)
+            }
+            // check::sentence で確かめてある
+            _ => unreachable!(),
+        }
+    }
+}
+
+// 代入先をたどる一段
+enum Key<'a> {
+    // 添字を付ける式の位置と，添字
+    Index(&'a pos::Range, (&'a pos::Range, Value)),
+    Member(&'a expr::PName),
+}
+
+// 変数からたどった代入先の値
+fn load(scope: &Scope, (pos, name): (&pos::Range, &str), path: &[Key]) -> Result<Value, Error> {
+    let mut value = lookup(name, scope).ok_or_else(|| Error::UndefinedVariable(pos.clone()))?;
+    for key in path {
+        value = match key {
+            Key::Index(container_pos, (index_pos, index)) => {
+                operator::index((container_pos, value), (index_pos, index.clone()))?
+            }
+            Key::Member(name) => operator::member(value, name)?,
+        };
+    }
+    Ok(value)
+}
+
+// 変数からたどった代入先に代入する
+fn store(
+    scope: &Scope,
+    (pos, name): (&pos::Range, &str),
+    path: Vec<Key>,
+    value: Value,
+) -> Result<Value, Error> {
+    if path.is_empty() {
+        scope.assign(name, value.clone());
+        return Ok(value);
+    }
+    scope
+        .modify(name, |mut variable| {
+            for key in path {
+                variable = match key {
+                    Key::Index(container_pos, index) => {
+                        operator::element_mut((container_pos, variable), index)?
+                    }
+                    Key::Member(name) => operator::member_mut(variable, name)?,
+                };
+            }
+            *variable = value.clone();
+            Ok(value)
+        })
+        .unwrap_or_else(|| Err(Error::UndefinedVariable(pos.clone())))
+}
+
+// `a.f(...)` で呼ぶ関数と，先頭に加える引数
+//
+// a のフィールドやモジュールの変数に f がなければ，`f(a, ...)` として呼ぶ
+fn method(
+    (object_pos, object): (&pos::Range, Value),
+    member_name: &expr::PName,
+    scope: &Scope,
+) -> Result<(Value, Option<PValue>), Error> {
+    let (pos, name) = member_name;
+    let member = match &object {
+        Value::Record(record) => record.get(name).cloned(),
+        Value::Module(_) => return Ok((operator::member(object, member_name)?, None)),
+        _ => None,
+    };
+    if let Some(member) = member {
+        return Ok((member, None));
+    }
+    match lookup(name, scope) {
+        Some(fnc) => Ok((fnc, Some((object_pos.clone(), object)))),
+        None => Err(Error::UndefinedMember(pos.clone(), object.type_name())),
+    }
+}
+
+// 変数を探し，なければ組み込み関数を探す
+fn lookup(name: &str, scope: &Scope) -> Option<Value> {
+    scope
+        .get(name)
+        .or_else(|| builtin::get(name).map(Value::Builtin))
+}
+
+// 引数を仮引数の並びに揃える（省略されたものは None）
+//
+// params は仮引数の名前と省略できるかどうか
+fn bind_args<'a>(
+    pos: &pos::Range,
+    params: impl Iterator<Item = (&'a str, bool)>,
+    args: Vec<PValue>,
+    named_args: Vec<(expr::PName, PValue)>,
+) -> Result<Vec<Option<PValue>>, Error> {
+    let params: Vec<_> = params.collect();
+    if args.len() > params.len() {
+        return Err(Error::TooManyArguments(
+            pos.clone(),
+            params.len(),
+            args.len(),
+        ));
+    }
+    let mut ret: Vec<_> = args.into_iter().map(Some).collect();
+    ret.resize(params.len(), None);
+    for ((name_pos, name), arg) in named_args {
+        let index = params
+            .iter()
+            .position(|&(param, _)| param == name)
+            .ok_or_else(|| Error::UnknownArgument(name_pos.clone()))?;
+        if ret[index].is_some() {
+            return Err(Error::DuplicateArgument(name_pos));
+        }
+        ret[index] = Some(arg);
+    }
+    for (arg, &(name, optional)) in ret.iter().zip(&params) {
+        if arg.is_none() && !optional {
+            return Err(Error::MissingArgument(pos.clone(), name.to_owned()));
+        }
+    }
+    Ok(ret)
+}
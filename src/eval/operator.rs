@@ -0,0 +1,308 @@
+use std::rc::Rc;
+
+use crate::error::Error;
+use crate::expr::{BinOp, PName, UnOp};
+use crate::pos;
+use crate::sound::{self, Op, Signal, Sound};
+use crate::value::Value;
+
+// 演算子は位置と種類（位置は誤りを報告するときだけ複製する）
+pub fn unary(op: (&pos::Range, UnOp), operand: (&pos::Range, Value)) -> Result<Value, Error> {
+    let (operand_pos, operand) = operand;
+    if let (UnOp::LogicalNot, Some(truth)) = (op.1, truth(&operand)) {
+        return Ok(Value::Boolean(!truth));
+    }
+    match (op.1, operand) {
+        (UnOp::Plus, operand @ (Value::Integer(_) | Value::Float(_))) => Ok(operand),
+        (UnOp::Minus, Value::Integer(n)) => Ok(Value::Integer(n.wrapping_neg())),
+        (UnOp::Minus, Value::Float(x)) => Ok(Value::Float(-x)),
+        (UnOp::Plus, operand @ Value::Sound(_)) => Ok(operand),
+        (UnOp::Minus, Value::Sound(sound)) => Ok(Value::Sound(sound.map(Signal::neg).into())),
+        // 逆数（整数は浮動小数点数にする）
+        (UnOp::Recip, Value::Integer(n)) => Ok(Value::Float(1. / f64::from(n))),
+        (UnOp::Recip, Value::Float(x)) => Ok(Value::Float(1. / x)),
+        (UnOp::Recip, Value::Sound(sound)) => Ok(Value::Sound(sound.map(Signal::recip).into())),
+        (UnOp::BitNot, Value::Integer(n)) => Ok(Value::Integer(!n)),
+        // 増減した後の値
+        (UnOp::PreIncrement | UnOp::PostIncrement, Value::Integer(n)) => {
+            Ok(Value::Integer(n.wrapping_add(1)))
+        }
+        (UnOp::PreDecrement | UnOp::PostDecrement, Value::Integer(n)) => {
+            Ok(Value::Integer(n.wrapping_sub(1)))
+        }
+        (UnOp::PreIncrement | UnOp::PostIncrement, Value::Float(x)) => Ok(Value::Float(x + 1.)),
+        (UnOp::PreDecrement | UnOp::PostDecrement, Value::Float(x)) => Ok(Value::Float(x - 1.)),
+        (_, operand) => Err(Error::InvalidOperand(
+            op.0.clone(),
+            operand_pos.clone(),
+            operand.type_name(),
+        )),
+    }
+}
+
+// 条件としての真偽（条件にできない値なら None）
+//
+// 真偽値のほか，数は 0 でなければ真とする
+pub fn truth(value: &Value) -> Option<bool> {
+    match *value {
+        Value::Boolean(value) => Some(value),
+        Value::Integer(n) => Some(n != 0),
+        Value::Float(x) => Some(x != 0.),
+        _ => None,
+    }
+}
+
+pub fn binary(
+    op: (&pos::Range, BinOp),
+    left: (&pos::Range, Value),
+    right: (&pos::Range, Value),
+) -> Result<Value, Error> {
+    let ((left_pos, left), (right_pos, right)) = (left, right);
+    let result = match (&left, &right) {
+        (&Value::Integer(l), &Value::Integer(r)) => integer(op, l, r, right_pos)?,
+        (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+            float(op.1, to_float(&left), to_float(&right))
+        }
+        (Value::String(l), Value::String(r)) => string(op.1, l, r),
+        (Value::Boolean(l), Value::Boolean(r)) => boolean(op.1, *l, *r),
+        (Value::Sound(l), Value::Sound(r)) => sound_sound(op.1, l, r),
+        (Value::Sound(l), Value::Integer(_) | Value::Float(_)) => {
+            sound_number(op.1, l, to_float(&right))
+        }
+        (Value::Integer(_) | Value::Float(_), Value::Sound(r)) => {
+            number_sound(op.1, to_float(&left), r)
+        }
+        (Value::List(_) | Value::Record(_), _) | (_, Value::List(_) | Value::Record(_)) => {
+            match op.1 {
+                BinOp::Equal => Some(Value::Boolean(equal(&left, &right))),
+                BinOp::NotEqual => Some(Value::Boolean(!equal(&left, &right))),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    result.ok_or_else(|| {
+        Error::InvalidOperands(left_pos + right_pos, left.type_name(), right.type_name())
+    })
+}
+
+fn to_float(value: &Value) -> f64 {
+    match *value {
+        Value::Integer(n) => n.into(),
+        Value::Float(x) => x,
+        _ => unreachable!(),
+    }
+}
+
+// 整数の演算は溢れたら折り返す．ただし `**` は溢れたら浮動小数点数にし，
+// シフトの量が 0 以上 32 未満でなければ誤りにする
+//
+// 除算は 0 に向かって切り捨て，剰余は割られる数と同じ符号になる（`a == a / b * b + a % b`）
+fn integer(
+    op: (&pos::Range, BinOp),
+    l: i32,
+    r: i32,
+    right_pos: &pos::Range,
+) -> Result<Option<Value>, Error> {
+    if matches!(op.1, BinOp::Div | BinOp::Rem) && r == 0 {
+        return Err(Error::DivisionByZero(op.0.clone(), right_pos.clone()));
+    }
+    // 負の指数なら浮動小数点数にする
+    if op.1 == BinOp::Pow && r < 0 {
+        return Ok(float(op.1, l.into(), r.into()));
+    }
+    if matches!(op.1, BinOp::LeftShift | BinOp::RightShift) && !(0..32).contains(&r) {
+        return Err(Error::ShiftOutOfRange(op.0.clone(), right_pos.clone(), r));
+    }
+    let result = match op.1 {
+        BinOp::Add => l.wrapping_add(r),
+        BinOp::Sub => l.wrapping_sub(r),
+        BinOp::Mul => l.wrapping_mul(r),
+        BinOp::Div => l.wrapping_div(r),
+        BinOp::Rem => l.wrapping_rem(r),
+        BinOp::Pow => match l.checked_pow(r as u32) {
+            Some(result) => result,
+            None => return Ok(float(op.1, l.into(), r.into())),
+        },
+        BinOp::LeftShift => l << r,
+        BinOp::RightShift => l >> r,
+        BinOp::BitAnd => l & r,
+        BinOp::BitOr => l | r,
+        BinOp::BitXor => l ^ r,
+        op => return Ok(compare(op, &l, &r)),
+    };
+    Ok(Some(Value::Integer(result)))
+}
+
+// 浮動小数点数の除算は 0 で割っても誤りにしない（無限大か NaN になる）
+fn float(op: BinOp, l: f64, r: f64) -> Option<Value> {
+    let result = match op {
+        BinOp::Add => l + r,
+        BinOp::Sub => l - r,
+        BinOp::Mul => l * r,
+        BinOp::Div => l / r,
+        BinOp::Rem => l % r,
+        BinOp::Pow => l.powf(r),
+        op => return compare(op, &l, &r),
+    };
+    Some(Value::Float(result))
+}
+
+fn string(op: BinOp, l: &str, r: &str) -> Option<Value> {
+    match op {
+        BinOp::Add => Some(Value::String(format!("{l}{r}").into())),
+        op => compare(op, l, r),
+    }
+}
+
+// 音どうしは重ねる（`+` `-`）か掛ける（`*`）
+//
+// チャンネルごとに演算し，モノラルの音は相手の各チャンネルに使う
+fn sound_sound(op: BinOp, l: &Sound, r: &Sound) -> Option<Value> {
+    let op = match op {
+        BinOp::Add => Op::Add,
+        BinOp::Sub => Op::Sub,
+        BinOp::Mul => Op::Mul,
+        _ => return None,
+    };
+    let sound = l.zip(r, |l, r| l.zip(op, r))?;
+    Some(Value::Sound(sound.into()))
+}
+
+// 数は音の各サンプルに対して演算する．`>>>` `<<<` は秒数だけ遅らせる・早める
+fn sound_number(op: BinOp, l: &Sound, r: f64) -> Option<Value> {
+    let sound = match op {
+        BinOp::ForwardShift => l.shift(sound::ticks(r)),
+        BinOp::BackwardShift => l.shift(-sound::ticks(r)),
+        op => {
+            let op = sample_op(op)?;
+            l.map(|l| l.left(op, r))
+        }
+    };
+    Some(Value::Sound(sound.into()))
+}
+
+fn number_sound(op: BinOp, l: f64, r: &Sound) -> Option<Value> {
+    let op = sample_op(op)?;
+    Some(Value::Sound(r.map(|r| r.right(op, l)).into()))
+}
+
+// 数と音の各サンプルの演算
+fn sample_op(op: BinOp) -> Option<Op> {
+    match op {
+        BinOp::Add => Some(Op::Add),
+        BinOp::Sub => Some(Op::Sub),
+        BinOp::Mul => Some(Op::Mul),
+        BinOp::Div => Some(Op::Div),
+        BinOp::Pow => Some(Op::Pow),
+        _ => None,
+    }
+}
+
+// 等しいかどうかのほか，`&` `|` `^` は短絡しない論理演算
+fn boolean(op: BinOp, l: bool, r: bool) -> Option<Value> {
+    let result = match op {
+        BinOp::Equal => l == r,
+        BinOp::NotEqual => l != r,
+        BinOp::BitAnd => l & r,
+        BinOp::BitOr => l | r,
+        BinOp::BitXor => l ^ r,
+        _ => return None,
+    };
+    Some(Value::Boolean(result))
+}
+
+// 構造による等しさ
+//
+// レコードはフィールドの順番によらず，同じ名前のフィールドが等しければ等しい
+fn equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Void, Value::Void) => true,
+        (Value::Boolean(l), Value::Boolean(r)) => l == r,
+        (&Value::Integer(l), &Value::Integer(r)) => l == r,
+        (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+            to_float(left) == to_float(right)
+        }
+        (Value::String(l), Value::String(r)) => l == r,
+        (Value::List(l), Value::List(r)) => {
+            l.len() == r.len() && l.iter().zip(r.iter()).all(|(l, r)| equal(l, r))
+        }
+        (Value::Record(l), Value::Record(r)) => {
+            l.fields.len() == r.fields.len()
+                && l.fields
+                    .iter()
+                    .all(|(name, l)| r.get(name).is_some_and(|r| equal(l, r)))
+        }
+        (Value::Module(l), Value::Module(r)) => Rc::ptr_eq(l, r),
+        (Value::Builtin(l), Value::Builtin(r)) => l.name == r.name,
+        (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
+        _ => false,
+    }
+}
+
+// 比較の結果は真偽値
+fn compare<T: PartialOrd + ?Sized>(op: BinOp, l: &T, r: &T) -> Option<Value> {
+    let result = match op {
+        BinOp::Equal => l == r,
+        BinOp::NotEqual => l != r,
+        BinOp::Less => l < r,
+        BinOp::Greater => l > r,
+        BinOp::LessEqual => l <= r,
+        BinOp::GreaterEqual => l >= r,
+        _ => return None,
+    };
+    Some(Value::Boolean(result))
+}
+
+// `xs[i]` の値
+pub fn index(container: (&pos::Range, Value), index: (&pos::Range, Value)) -> Result<Value, Error> {
+    match container {
+        (_, Value::List(elems)) => Ok(elems[position(index, elems.len())?].clone()),
+        (pos, container) => Err(Error::NotIndexable(pos.clone(), container.type_name())),
+    }
+}
+
+// 代入のため `xs[i]` の場所を得る（共有されているリストは複製する）
+pub fn element_mut<'a>(
+    container: (&pos::Range, &'a mut Value),
+    index: (&pos::Range, Value),
+) -> Result<&'a mut Value, Error> {
+    match container {
+        (_, Value::List(elems)) => {
+            let elems = Rc::make_mut(elems);
+            let i = position(index, elems.len())?;
+            Ok(&mut elems[i])
+        }
+        (pos, container) => Err(Error::NotIndexable(pos.clone(), container.type_name())),
+    }
+}
+
+// `a.b` の値
+pub fn member(object: Value, (pos, name): &PName) -> Result<Value, Error> {
+    let value = match &object {
+        Value::Record(record) => record.get(name).cloned(),
+        Value::Module(module) => module.scope.get(name),
+        _ => None,
+    };
+    value.ok_or_else(|| Error::UndefinedMember(pos.clone(), object.type_name()))
+}
+
+// 代入のため `a.b` の場所を得る（モジュールの変数には代入できない）
+pub fn member_mut<'a>(object: &'a mut Value, (pos, name): &PName) -> Result<&'a mut Value, Error> {
+    let object_type = object.type_name();
+    match object {
+        Value::Record(record) => Rc::make_mut(record).get_mut(name),
+        _ => None,
+    }
+    .ok_or_else(|| Error::UndefinedMember(pos.clone(), object_type))
+}
+
+fn position((pos, index): (&pos::Range, Value), len: usize) -> Result<usize, Error> {
+    match index {
+        Value::Integer(n) => usize::try_from(n)
+            .ok()
+            .filter(|&i| i < len)
+            .ok_or_else(|| Error::IndexOutOfRange(pos.clone(), n, len)),
+        index => Err(Error::InvalidIndex(pos.clone(), index.type_name())),
+    }
+}
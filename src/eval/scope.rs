@@ -0,0 +1,112 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::value::Value;
+
+// 変数の有効範囲
+//
+// 見つからない変数は外側の有効範囲から探す
+pub struct Scope {
+    variables: RefCell<HashMap<String, Value>>,
+    // バイトコードで呼んだ関数の仮引数（まだ値が決まっていないものは None）
+    //
+    // 名前でも読み書きでき，翻訳したコードは番号で読み書きする
+    slots: RefCell<Vec<Option<Value>>>,
+    slot_names: Option<Rc<[String]>>,
+    parent: Option<Rc<Scope>>,
+}
+
+impl Scope {
+    pub fn new(parent: Option<Rc<Scope>>) -> Rc<Scope> {
+        Rc::new(Scope {
+            variables: RefCell::new(HashMap::new()),
+            slots: RefCell::new(Vec::new()),
+            slot_names: None,
+            parent,
+        })
+    }
+    // 仮引数を番号で読み書きする有効範囲
+    pub fn with_slots(parent: Rc<Scope>, names: Rc<[String]>) -> Rc<Scope> {
+        Rc::new(Scope {
+            variables: RefCell::new(HashMap::new()),
+            slots: RefCell::new(vec![None; names.len()]),
+            slot_names: Some(names),
+            parent: Some(parent),
+        })
+    }
+    pub fn get(&self, name: &str) -> Option<Value> {
+        if let Some(value) = self.slot(name).and_then(|i| self.slots.borrow()[i].clone()) {
+            return Some(value);
+        }
+        match self.variables.borrow().get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.parent.as_ref()?.get(name),
+        }
+    }
+    // 番号で指した仮引数の値（関数の本体を実行している間は決まっている）
+    pub fn get_slot(&self, index: usize) -> Value {
+        self.slots.borrow()[index].clone().unwrap()
+    }
+    pub fn set_slot(&self, index: usize, value: Value) {
+        self.slots.borrow_mut()[index] = Some(value);
+    }
+    // 外側の有効範囲に同じ名前の変数があればそれを書き換え，
+    // なければこの有効範囲に作る
+    pub fn assign(&self, name: &str, value: Value) {
+        if !self.replace(name, &value) {
+            self.define(name, value);
+        }
+    }
+    pub fn define(&self, name: &str, value: Value) {
+        match self.slot(name) {
+            Some(index) => self.set_slot(index, value),
+            None => {
+                self.variables.borrow_mut().insert(name.to_owned(), value);
+            }
+        }
+    }
+    // 変数を見つけてその場で書き換える
+    pub fn modify<T>(&self, name: &str, f: impl FnOnce(&mut Value) -> T) -> Option<T> {
+        if let Some(index) = self.slot(name) {
+            if let Some(variable) = &mut self.slots.borrow_mut()[index] {
+                return Some(f(variable));
+            }
+        }
+        match self.variables.borrow_mut().get_mut(name) {
+            Some(variable) => Some(f(variable)),
+            None => self.parent.as_ref()?.modify(name, f),
+        }
+    }
+    pub fn parent(&self) -> Option<&Rc<Scope>> {
+        self.parent.as_ref()
+    }
+    // この有効範囲の変数の名前（順は定まらない）
+    #[cfg(test)]
+    pub fn names(&self) -> Vec<String> {
+        self.variables.borrow().keys().cloned().collect()
+    }
+    fn slot(&self, name: &str) -> Option<usize> {
+        self.slot_names
+            .as_ref()?
+            .iter()
+            .position(|slot| slot == name)
+    }
+    fn replace(&self, name: &str, value: &Value) -> bool {
+        if let Some(index) = self.slot(name) {
+            if let Some(variable) = &mut self.slots.borrow_mut()[index] {
+                *variable = value.clone();
+                return true;
+            }
+        }
+        if let Some(variable) = self.variables.borrow_mut().get_mut(name) {
+            *variable = value.clone();
+            true
+        } else {
+            match &self.parent {
+                Some(parent) => parent.replace(name, value),
+                None => false,
+            }
+        }
+    }
+}
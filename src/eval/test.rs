@@ -0,0 +1,1001 @@
+#![cfg(test)]
+
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use super::{Backend, Interpreter, Scope};
+use crate::error::Error;
+use crate::lexer::Lexer;
+use crate::value::{Module, Value};
+
+// 構文木をたどった結果とバイトコードを実行した結果が一致することも確かめる
+fn run(code: &str) -> Result<Rc<Scope>, Error> {
+    let tree = run_on(Backend::Tree, code);
+    let vm = run_on(Backend::Vm, code);
+    match (&tree, &vm) {
+        (Ok(tree), Ok(vm)) => {
+            let mut names = tree.names();
+            names.sort();
+            let mut vm_names = vm.names();
+            vm_names.sort();
+            assert_eq!(names, vm_names);
+            for name in names {
+                let (tree, vm) = (tree.get(&name).unwrap(), vm.get(&name).unwrap());
+                assert_eq!(tree.to_string(), vm.to_string(), "{name}");
+            }
+        }
+        (Err(tree), Err(vm)) => {
+            assert_eq!(std::mem::discriminant(tree), std::mem::discriminant(vm));
+        }
+        _ => panic!("the backends disagree"),
+    }
+    vm
+}
+
+fn run_on(backend: Backend, code: &str) -> Result<Rc<Scope>, Error> {
+    let mut interpreter = Interpreter::new();
+    interpreter.set_backend(backend);
+    let mut lexer = Lexer::from_str("test", code);
+    let scope = Scope::new(None);
+    interpreter.run(&mut lexer, &scope)?;
+    Ok(scope)
+}
+
+fn ok<T>(result: Result<T, Error>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            err.eprint();
+            panic!();
+        }
+    }
+}
+
+fn integer(scope: &Scope, name: &str) -> i32 {
+    match scope.get(name) {
+        Some(Value::Integer(n)) => n,
+        _ => panic!("{name} is not an integer"),
+    }
+}
+
+fn boolean(scope: &Scope, name: &str) -> bool {
+    match scope.get(name) {
+        Some(Value::Boolean(value)) => value,
+        _ => panic!("{name} is not a boolean"),
+    }
+}
+
+fn module(scope: &Scope, name: &str) -> Rc<Module> {
+    match scope.get(name) {
+        Some(Value::Module(module)) => module,
+        _ => panic!("{name} is not a module"),
+    }
+}
+
+// 一時ディレクトリにファイルを作る
+fn files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("cryss-{}-{test}", std::process::id()));
+    for (name, content) in files {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+fn run_file(path: &Path) -> Result<Rc<Module>, Error> {
+    let mut interpreter = Interpreter::new();
+    let lexer = Lexer::from_path(path).unwrap();
+    interpreter.run_file(lexer, path)
+}
+
+#[test]
+fn assignment() {
+    let scope = ok(run("a = 1 + 2 * 3; b = a; a -= 4; c = (a << 2) % 5;"));
+    assert_eq!(integer(&scope, "a"), 3);
+    assert_eq!(integer(&scope, "b"), 7);
+    assert_eq!(integer(&scope, "c"), 2);
+}
+
+#[test]
+fn import() {
+    let dir = files(
+        "import",
+        &[
+            ("main.cry", "import \"lib/drums.cry\"; kick = drums;"),
+            (
+                "lib/drums.cry",
+                "import \"common.cry\"; kick = common; snare = 38;",
+            ),
+            ("lib/common.cry", "base = 36;"),
+        ],
+    );
+    let main = ok(run_file(&dir.join("main.cry")));
+    std::fs::remove_dir_all(dir).unwrap();
+    let drums = module(&main.scope, "drums");
+    assert_eq!(drums.name, "drums");
+    assert_eq!(integer(&drums.scope, "snare"), 38);
+    assert!(drums.scope.get("base").is_none());
+    let common = module(&drums.scope, "kick");
+    assert_eq!(integer(&common.scope, "base"), 36);
+    assert!(Rc::ptr_eq(&drums, &module(&main.scope, "kick")));
+}
+
+// ファイル名が識別子にならないときや，同じ名前のファイルを読むときは as で名前を与える
+#[test]
+fn import_as() {
+    let dir = files(
+        "import_as",
+        &[
+            (
+                "main.cry",
+                "import \"a/drums.cry\"; import \"b/drums.cry\" as b_drums;
+                 import \"my-drums.cry\" as mine; import \"a/drums.cry\";",
+            ),
+            ("invalid.cry", "import \"my-drums.cry\";"),
+            (
+                "conflict.cry",
+                "import \"a/drums.cry\"; import \"b/drums.cry\";",
+            ),
+            ("a/drums.cry", "kick = 36;"),
+            ("b/drums.cry", "kick = 35;"),
+            ("my-drums.cry", "kick = 34;"),
+        ],
+    );
+    let main = run_file(&dir.join("main.cry"));
+    let invalid = run_file(&dir.join("invalid.cry"));
+    let conflict = run_file(&dir.join("conflict.cry"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    let main = ok(main);
+    assert_eq!(integer(&module(&main.scope, "drums").scope, "kick"), 36);
+    assert_eq!(integer(&module(&main.scope, "b_drums").scope, "kick"), 35);
+    assert_eq!(integer(&module(&main.scope, "mine").scope, "kick"), 34);
+    match invalid {
+        Err(Error::InvalidModuleName(pos, name)) => {
+            assert_eq!(name, "my-drums");
+            assert_eq!(format!("{pos:?}"), "0:7-0:21");
+        }
+        _ => panic!("invalid module name not detected"),
+    }
+    match conflict {
+        Err(Error::ModuleNameConflict(pos, name)) => {
+            assert_eq!(name, "drums");
+            assert_eq!(format!("{pos:?}"), "0:29-0:42");
+        }
+        _ => panic!("module name conflict not detected"),
+    }
+}
+
+#[test]
+fn import_cycle() {
+    let dir = files(
+        "import_cycle",
+        &[
+            ("a.cry", "import \"b.cry\";"),
+            ("b.cry", "x = 1;\nimport \"a.cry\";"),
+        ],
+    );
+    let result = run_file(&dir.join("a.cry"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    match result {
+        Err(Error::InImportedFile(pos, error)) => {
+            assert_eq!(format!("{pos:?}"), "0:7-0:14");
+            match *error {
+                Error::ImportCycle(pos, cycle) => {
+                    assert!(pos.to_string().ends_with("b.cry:2:8-2:14"));
+                    assert_eq!(cycle.len(), 2);
+                    assert!(cycle[0].ends_with("a.cry"));
+                    assert!(cycle[1].ends_with("b.cry"));
+                }
+                _ => panic!("not an import cycle"),
+            }
+        }
+        _ => panic!("import cycle not detected"),
+    }
+}
+
+#[test]
+fn error_in_imported_file() {
+    let dir = files(
+        "error_in_imported_file",
+        &[
+            ("main.cry", "import \"sub.cry\";"),
+            ("sub.cry", "x = 1;\ny = z;"),
+        ],
+    );
+    let result = run_file(&dir.join("main.cry"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    match result {
+        Err(Error::InImportedFile(_, error)) => match *error {
+            Error::UndefinedVariable(pos) => {
+                assert!(pos.to_string().ends_with("sub.cry:2:5-2:5"))
+            }
+            _ => panic!("not an undefined variable"),
+        },
+        _ => panic!("error in the imported file not reported"),
+    }
+}
+
+#[test]
+fn function() {
+    let scope = ok(run("
+        predecessor(n) {
+            {
+                m = n - 1;
+            }
+            return n - 1;
+        }
+        make_counter(step) {
+            count = 0;
+            next() {
+                count += step;
+                return count;
+            }
+            return next;
+        }
+        counter = make_counter(2);
+        counter();
+        a = counter();
+        b = predecessor(5);
+        nothing() { return; }
+        c = nothing;
+    "));
+    assert_eq!(integer(&scope, "a"), 4);
+    assert_eq!(integer(&scope, "b"), 4);
+    assert!(scope.get("count").is_none());
+    assert!(scope.get("m").is_none());
+    assert!(matches!(scope.get("c"), Some(Value::Function(_))));
+}
+
+// 引数の読み書きは枠の番号で行うが，見え方は名前で探すときと変わらない
+#[test]
+fn parameters() {
+    let scope = ok(run("
+        f(x) {
+            {
+                x() { return 5; }
+                y = x();
+            }
+            return x;
+        }
+        g(n) {
+            inc() { n++; }
+            inc();
+            inc();
+            return n;
+        }
+        h(n, m: n * 2) { return m; }
+        k(v) {
+            v += 1;
+            v++;
+            return v;
+        }
+        a = f(1);
+        b = g(1);
+        c = h(4);
+        d = k(1);
+    "));
+    assert_eq!(integer(&scope, "a"), 1);
+    assert_eq!(integer(&scope, "b"), 3);
+    assert_eq!(integer(&scope, "c"), 8);
+    assert_eq!(integer(&scope, "d"), 3);
+}
+
+#[test]
+fn recursion() {
+    let scope = ok(run("
+        sum(n) {
+            total += n;
+            return n > 0 && sum(n - 1);
+        }
+        total = 0;
+        sum(10);
+    "));
+    assert_eq!(integer(&scope, "total"), 55);
+}
+
+#[test]
+fn function_errors() {
+    assert!(matches!(
+        run("f(x) { return x; } f(1, 2);"),
+        Err(Error::TooManyArguments(_, 1, 2))
+    ));
+    assert!(matches!(
+        run("{ return 1; }"),
+        Err(Error::ReturnOutsideFunction(_))
+    ));
+    let too_deep = std::thread::Builder::new()
+        .stack_size(super::STACK_SIZE)
+        .spawn(|| {
+            matches!(
+                run("f() { return f(); } f();"),
+                Err(Error::TooDeepRecursion(_))
+            )
+        })
+        .unwrap();
+    assert!(too_deep.join().unwrap());
+    assert!(matches!(run("f(1) { }"), Err(Error::InvalidParameter(_))));
+    assert!(matches!(run("f(x) { x;"), Err(Error::NoClosingBrace(_))));
+}
+
+#[test]
+fn named_and_default_arguments() {
+    let scope = ok(run("
+        adsr(a, d, s: 1, r: a * 2) { return a * 1000 + d * 100 + s * 10 + r; }
+        x = adsr(1, 2);
+        y = adsr(1, 2, 3, 4);
+        z = adsr(r: 5, a: 1, d: 2);
+        w = adsr(3, d: 0, s: 0);
+    "));
+    assert_eq!(integer(&scope, "x"), 1212);
+    assert_eq!(integer(&scope, "y"), 1234);
+    assert_eq!(integer(&scope, "z"), 1215);
+    assert_eq!(integer(&scope, "w"), 3006);
+}
+
+#[test]
+fn argument_errors() {
+    let definition = "f(a, b: 1) { return a + b; } ";
+    let run = |call: &str| run(&format!("{definition}{call}"));
+    assert!(matches!(run("f(1, c: 2);"), Err(Error::UnknownArgument(_))));
+    assert!(matches!(
+        run("f(1, a: 2);"),
+        Err(Error::DuplicateArgument(_))
+    ));
+    assert!(matches!(
+        run("f(a: 1, a: 2);"),
+        Err(Error::DuplicateArgument(_))
+    ));
+    assert!(matches!(run("f(b: 2);"), Err(Error::MissingArgument(_, name)) if name == "a"));
+    assert!(matches!(
+        run("f(a: 1, 2);"),
+        Err(Error::PositionalArgumentAfterNamed(_))
+    ));
+    assert!(matches!(
+        run("f(1 + 1: 2);"),
+        Err(Error::InvalidArgumentName(_))
+    ));
+    assert!(matches!(
+        run("f(a: );"),
+        Err(Error::NoArgumentAfterColon(_))
+    ));
+    assert!(matches!(
+        run("print(x: 1);"),
+        Err(Error::UnknownArgument(_))
+    ));
+    assert!(matches!(
+        run("g(a, a) {}"),
+        Err(Error::DuplicateParameter(_))
+    ));
+    assert!(matches!(
+        run("g(a: 1, a) {}"),
+        Err(Error::PositionalArgumentAfterNamed(_))
+    ));
+}
+
+#[test]
+fn conditional() {
+    let scope = ok(run("
+        sign(x) { return x < 0 ? -1 : x == 0 ? 0 : 1; }
+        a = sign(-5);
+        b = sign(0);
+        c = sign(7);
+        d = 1 ? 2 : 3 || 4;
+        e = 0 ? f : 10;
+        g = 1 ? 0 ? 1 : 2 : 3;
+    "));
+    assert_eq!(integer(&scope, "a"), -1);
+    assert_eq!(integer(&scope, "b"), 0);
+    assert_eq!(integer(&scope, "c"), 1);
+    assert_eq!(integer(&scope, "d"), 2);
+    assert_eq!(integer(&scope, "e"), 10);
+    assert_eq!(integer(&scope, "g"), 2);
+    assert!(matches!(
+        run("x = \"s\" ? 1 : 2;"),
+        Err(Error::InvalidCondition(_, "string"))
+    ));
+    assert!(matches!(
+        run("x = 1 ? 2;"),
+        Err(Error::UnexpectedTokenInConditional(..))
+    ));
+    assert!(matches!(
+        run("x = 1 ? 2 :"),
+        Err(Error::UnexpectedEOFInConditional(_))
+    ));
+}
+
+#[test]
+fn list() {
+    let scope = ok(run("
+        chord = [60, 64, 67];
+        root = chord[0];
+        n = len(chord);
+        copy = chord;
+        chord[1] = 63;
+        chord[2] += 1;
+        third = chord[1];
+        fifth = chord[2];
+        original = copy[1];
+        grid = [[1, 2], [3, 4]];
+        grid[1][0] = 5;
+        cell = grid[1][0];
+        empty = len([]);
+        xs = [1, 2, 3];
+        i = 0;
+        xs[i++] += 10;
+        first = xs[0];
+        m = [[1, 2], [3, 4]];
+        j = 0;
+        m[j++][j++] *= 10;
+        corner = m[0][1];
+        k = 0;
+        ys = [1, 2, 3];
+        old = ys[k++]++;
+        new = ++ys[k++];
+    "));
+    assert_eq!(integer(&scope, "root"), 60);
+    assert_eq!(integer(&scope, "n"), 3);
+    assert_eq!(integer(&scope, "third"), 63);
+    assert_eq!(integer(&scope, "fifth"), 68);
+    assert_eq!(integer(&scope, "original"), 64);
+    assert_eq!(integer(&scope, "cell"), 5);
+    assert_eq!(integer(&scope, "empty"), 0);
+    // 複合代入の添字は 1 回だけ評価する
+    assert_eq!(integer(&scope, "first"), 11);
+    assert_eq!(integer(&scope, "i"), 1);
+    assert_eq!(integer(&scope, "corner"), 20);
+    assert_eq!(integer(&scope, "j"), 2);
+    assert_eq!(integer(&scope, "old"), 1);
+    assert_eq!(integer(&scope, "new"), 3);
+    assert_eq!(integer(&scope, "k"), 2);
+    assert_eq!(scope.get("ys").unwrap().to_string(), "[2, 3, 3]");
+    assert!(matches!(
+        run("xs = [1, 2]; xs[2];"),
+        Err(Error::IndexOutOfRange(_, 2, 2))
+    ));
+    assert!(matches!(
+        run("xs = [1, 2]; xs[-1] = 0;"),
+        Err(Error::IndexOutOfRange(_, -1, 2))
+    ));
+    assert!(matches!(
+        run("[1][\"a\"];"),
+        Err(Error::InvalidIndex(_, "string"))
+    ));
+    assert!(matches!(
+        run("x = 1; x[0] = 2;"),
+        Err(Error::NotIndexable(_, "integer"))
+    ));
+    assert!(matches!(
+        run("xs[0] = 1;"),
+        Err(Error::UndefinedVariable(_))
+    ));
+    assert!(matches!(
+        run("xs = [1, 2;"),
+        Err(Error::UnexpectedTokenInBracket(..))
+    ));
+    assert!(matches!(run("xs = [1][];"), Err(Error::EmptyIndex(..))));
+}
+
+#[test]
+fn member() {
+    let dir = files(
+        "member",
+        &[
+            (
+                "main.cryss",
+                "import \"synth.cryss\";
+                twice(x) { return x * 2; }
+                a = synth.base;
+                b = synth.twice(4);
+                n = 5;
+                c = n.twice();
+                d = synth.twice(3).twice();
+                e = [1, 2, 3].len();",
+            ),
+            ("synth.cryss", "base = 440; twice(x) { return x * 2; }"),
+        ],
+    );
+    let main = ok(run_file(&dir.join("main.cryss")));
+    assert_eq!(integer(&main.scope, "a"), 440);
+    assert_eq!(integer(&main.scope, "b"), 8);
+    assert_eq!(integer(&main.scope, "c"), 10);
+    assert_eq!(integer(&main.scope, "d"), 12);
+    assert_eq!(integer(&main.scope, "e"), 3);
+    std::fs::remove_dir_all(dir).unwrap();
+    assert!(matches!(
+        run("x = 1; x.y;"),
+        Err(Error::UndefinedMember(_, "integer"))
+    ));
+    assert!(matches!(
+        run("x = 1; x.f();"),
+        Err(Error::UndefinedMember(_, "integer"))
+    ));
+    // 括弧で囲むとメソッド呼び出しにならない
+    assert!(matches!(
+        run("xs = [1, 2]; n = (xs.len)();"),
+        Err(Error::UndefinedMember(_, "list"))
+    ));
+    assert!(matches!(
+        run("x = 1; x.y = 2;"),
+        Err(Error::UndefinedMember(_, "integer"))
+    ));
+    assert!(matches!(
+        run("x.;"),
+        Err(Error::UnexpectedTokenAfterDot(..))
+    ));
+}
+
+#[test]
+fn record() {
+    let scope = ok(run("
+        patch = { freq: 440, amp: 0.5, tones: [1, 2] };
+        freq = patch.freq;
+        copy = patch;
+        patch.freq = 880;
+        patch.tones[1] = 3;
+        changed = patch.freq;
+        original = copy.freq;
+        tone = patch.tones[1];
+        same = { a: 1, b: [2.0] } == { b: [2], a: 1 };
+        different = { a: 1 } == { a: 1, b: 2 };
+        not_equal = [1, 2] != [1, 3];
+        inner = 0;
+        {
+            inner = { x: 1 }.x;
+        }
+        gain(p, db) { return p.amp * db; }
+        g = patch.gain(2);
+    "));
+    assert_eq!(integer(&scope, "freq"), 440);
+    assert_eq!(integer(&scope, "changed"), 880);
+    assert_eq!(integer(&scope, "original"), 440);
+    assert_eq!(integer(&scope, "tone"), 3);
+    assert!(boolean(&scope, "same"));
+    assert!(!boolean(&scope, "different"));
+    assert!(boolean(&scope, "not_equal"));
+    assert_eq!(integer(&scope, "inner"), 1);
+    assert!(matches!(scope.get("g"), Some(Value::Float(x)) if x == 1.0));
+    assert!(matches!(
+        run("p = { a: 1 }; p.b = 2;"),
+        Err(Error::UndefinedMember(_, "record"))
+    ));
+    assert!(matches!(
+        run("p = { a: 1, a: 2 };"),
+        Err(Error::DuplicateField(_))
+    ));
+    assert!(matches!(
+        run("p = { a: 1, 2 };"),
+        Err(Error::NoFieldName(_))
+    ));
+    assert!(matches!(
+        run("p = { a: 1;"),
+        Err(Error::UnexpectedTokenInBrace(..))
+    ));
+}
+
+#[test]
+fn increment() {
+    let scope = ok(run("
+        i = 0;
+        a = i++;
+        b = ++i;
+        c = i--;
+        d = --i;
+        xs = [1, 2.5];
+        xs[0]++;
+        --xs[1];
+        p = { n: 1 };
+        p.n++;
+        x = xs[0];
+        n = p.n;
+    "));
+    assert_eq!(integer(&scope, "a"), 0);
+    assert_eq!(integer(&scope, "b"), 2);
+    assert_eq!(integer(&scope, "c"), 2);
+    assert_eq!(integer(&scope, "d"), 0);
+    assert_eq!(integer(&scope, "i"), 0);
+    assert_eq!(integer(&scope, "x"), 2);
+    assert_eq!(integer(&scope, "n"), 2);
+    assert!(
+        matches!(scope.get("xs"), Some(Value::List(xs)) if matches!(xs[1], Value::Float(x) if x == 1.5))
+    );
+    assert!(matches!(run("1++;"), Err(Error::InvalidAssignTarget(..))));
+    assert!(matches!(
+        run("s = \"a\"; s++;"),
+        Err(Error::InvalidOperand(..))
+    ));
+}
+
+// 後の文が構文や代入先の誤りを含めば，前の文も実行しない
+#[test]
+fn check_before_run() {
+    for code in ["a = 1;\nf(x) += 1;", "a = 1;\nb = ;"] {
+        for backend in [Backend::Tree, Backend::Vm] {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_backend(backend);
+            let mut lexer = Lexer::from_str("test", code);
+            let scope = Scope::new(None);
+            assert!(interpreter.run(&mut lexer, &scope).is_err());
+            assert!(scope.get("a").is_none());
+        }
+    }
+}
+
+#[test]
+fn logical() {
+    let scope = ok(run("
+        n = 0;
+        safe = n > 0 && 1 / n > 0.1;
+        count = 0;
+        touch() { count++; return true; }
+        a = true || touch();
+        b = false && touch();
+        c = false || touch();
+        d = !(1 < 2);
+        e = !0.0;
+        f = true == (2 >= 2);
+        g = true ^ false;
+        h = n == 0 ? \"zero\" : \"other\";
+    "));
+    assert!(!boolean(&scope, "safe"));
+    assert!(boolean(&scope, "a"));
+    assert!(!boolean(&scope, "b"));
+    assert!(boolean(&scope, "c"));
+    assert_eq!(integer(&scope, "count"), 1);
+    assert!(!boolean(&scope, "d"));
+    assert!(boolean(&scope, "e"));
+    assert!(boolean(&scope, "f"));
+    assert!(boolean(&scope, "g"));
+    assert!(matches!(scope.get("h"), Some(Value::String(s)) if &*s == "zero"));
+    assert!(matches!(
+        run("x = \"a\" && true;"),
+        Err(Error::InvalidOperand(_, _, "string"))
+    ));
+    assert!(matches!(run("x = !\"a\";"), Err(Error::InvalidOperand(..))));
+    assert!(matches!(
+        run("x = true + 1;"),
+        Err(Error::InvalidOperands(..))
+    ));
+}
+
+#[test]
+fn division() {
+    let scope = ok(run("
+        a = -7 / 2;
+        b = -7 % 2;
+        c = 7 % -2;
+        d = (-2147483647 - 1) / -1;
+        e = 7.0 / 2;
+        r = /4;
+        i = /0;
+    "));
+    assert_eq!(integer(&scope, "a"), -3);
+    assert_eq!(integer(&scope, "b"), -1);
+    assert_eq!(integer(&scope, "c"), 1);
+    assert_eq!(integer(&scope, "d"), i32::MIN);
+    assert!(matches!(scope.get("e"), Some(Value::Float(x)) if x == 3.5));
+    assert!(matches!(scope.get("r"), Some(Value::Float(x)) if x == 0.25));
+    assert!(matches!(scope.get("i"), Some(Value::Float(x)) if x == f64::INFINITY));
+    let division_by_zero = |code| match run(code) {
+        Err(Error::DivisionByZero(op, divisor)) => format!("{op:?} {divisor:?}"),
+        _ => panic!("{code}"),
+    };
+    assert_eq!(division_by_zero("x = 1 / (2 - 2);"), "0:6-0:7 0:8-0:15");
+    assert_eq!(division_by_zero("x = 1; x %= 0;"), "0:9-0:11 0:12-0:13");
+    assert!(matches!(
+        run("x = /\"a\";"),
+        Err(Error::InvalidOperand(_, _, "string"))
+    ));
+}
+
+#[test]
+fn sound() {
+    let scope = ok(run("
+        a = sine(440, 0.5);
+        b = a * 0.5 + (sine(220, 1) >>> 0.25);
+        period = /line(100, 200, 1);
+        fm = sine(line(0, 100, 0.1));
+    "));
+    let sound = |name| match scope.get(name) {
+        Some(Value::Sound(sound)) => sound,
+        _ => panic!("{name} is not a sound"),
+    };
+    let rate = crate::sound::SAMPLE_RATE as usize;
+    let ticks = crate::sound::TICKS;
+    let samples = |name| sound(name).channels()[0].to_vec(rate as u32);
+    assert_eq!(sound("a").len(), ticks / 2);
+    assert_eq!(sound("b").len(), ticks * 5 / 4);
+    assert!((samples("a")[rate / 1760] - 1.).abs() < 1e-3);
+    assert_eq!(samples("period")[0], 0.01);
+    assert_eq!(sound("fm").len(), ticks / 10);
+    assert!(matches!(
+        run("s = sine(440);"),
+        Err(Error::MissingArgument(_, name)) if name == "duration"
+    ));
+    assert!(matches!(
+        run("s = sine(\"a\", 1);"),
+        Err(Error::InvalidArgument(_, "string"))
+    ));
+    assert!(matches!(
+        run("s = sine(440, 1) / sine(440, 1);"),
+        Err(Error::InvalidOperands(..))
+    ));
+}
+
+#[test]
+fn pow() {
+    let scope = ok(run("
+        a = 2 ** 3 ** 2;
+        b = 2 * 3 ** 2;
+        c = 2 ** -1;
+        d = 2 ** (7 / 12.0);
+        e = 3;
+        e **= 2;
+        f = (-2) ** 3;
+        g = 2 ** 32;
+        h = (-2) ** 31;
+        i = 1 << 31;
+    "));
+    assert_eq!(integer(&scope, "a"), 512);
+    assert_eq!(integer(&scope, "b"), 18);
+    assert!(matches!(scope.get("c"), Some(Value::Float(x)) if x == 0.5));
+    assert!(matches!(scope.get("d"), Some(Value::Float(x)) if (x - 1.4983).abs() < 1e-4));
+    assert_eq!(integer(&scope, "e"), 9);
+    assert_eq!(integer(&scope, "f"), -8);
+    // 溢れたら浮動小数点数にする
+    assert!(matches!(scope.get("g"), Some(Value::Float(x)) if x == 4294967296.));
+    assert_eq!(integer(&scope, "h"), i32::MIN);
+    assert_eq!(integer(&scope, "i"), i32::MIN);
+    assert!(matches!(
+        run("x = 1 << 40;"),
+        Err(Error::ShiftOutOfRange(_, _, 40))
+    ));
+    assert!(matches!(
+        run("n = -1; x = 8 >> n;"),
+        Err(Error::ShiftOutOfRange(_, _, -1))
+    ));
+}
+
+#[test]
+fn chained_comparison() {
+    let scope = ok(run("
+        x = 5;
+        a = 0 < x < 10;
+        b = 0 < x < 3;
+        c = 1 <= 1 < 2 == 2;
+        d = (0 < x) == true;
+        g = x * 2 < 3;
+        count = 0;
+        touch() { count++; return 7; }
+        e = 10 < x < touch();
+        f = 0 < touch() < 10;
+    "));
+    assert!(boolean(&scope, "a"));
+    assert!(!boolean(&scope, "b"));
+    assert!(boolean(&scope, "c"));
+    assert!(boolean(&scope, "d"));
+    assert!(!boolean(&scope, "e"));
+    assert!(boolean(&scope, "f"));
+    assert!(!boolean(&scope, "g"));
+    assert_eq!(integer(&scope, "count"), 1);
+    assert!(matches!(
+        run("x = 0 < \"a\" < 1;"),
+        Err(Error::InvalidOperands(..))
+    ));
+}
+
+#[test]
+fn write() {
+    let dir = files("write", &[]);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = |name: &str| dir.join(name).display().to_string();
+    ok(run(&format!(
+        "
+        s = sine(440, 1) * 0.5 + (sine(660, 1) >>> 0.5) * 0.25 + sine(880, 0.1);
+        write(s, {:?}, threads: 1);
+        write(s, {:?}, threads: 3);
+    ",
+        path("one.wav"),
+        path("three.wav")
+    )));
+    let one = std::fs::read(path("one.wav")).unwrap();
+    assert_eq!(
+        one.len(),
+        44 + 2 * crate::sound::SAMPLE_RATE as usize * 3 / 2
+    );
+    assert_eq!(one, std::fs::read(path("three.wav")).unwrap());
+    // 別のサンプリング周波数で書き出して読み戻す
+    let scope = ok(run(&format!(
+        "
+        write(sine(440, 0.5), {:?}, rate: 8000);
+        r = read({:?});
+        s = r >>> 0.1 >>> 0.2;
+    ",
+        path("low.wav"),
+        path("low.wav")
+    )));
+    assert_eq!(std::fs::read(path("low.wav")).unwrap().len(), 44 + 2 * 4000);
+    assert_eq!(scope.get("s").unwrap().to_string(), "<sound 0.8s>");
+    assert!(matches!(
+        run(&format!("r = read({:?});", path("missing.wav"))),
+        Err(Error::CannotOpenFile(..))
+    ));
+    assert!(matches!(
+        run(&format!(
+            "write(sine(1, 1), {:?}, threads: 0);",
+            path("zero.wav")
+        )),
+        Err(Error::InvalidArgument(_, "integer"))
+    ));
+    // 1 秒あたりのバイト数が WAV のヘッダーに収まらない
+    assert!(matches!(
+        run(&format!(
+            "s = sine(1, 1); write(stereo(s, s), {:?}, rate: 2000000000);",
+            path("fast.wav")
+        )),
+        Err(Error::InvalidArgument(_, "integer"))
+    ));
+}
+
+#[test]
+fn stereo() {
+    let scope = ok(run("
+        m = sine(440, 1);
+        s = stereo(m, sine(220, 0.5));
+        p = pan(m, -1);
+        centre = pan(m, 0);
+        x = s + m;
+        y = line(0, 1, 2) * s;
+        l = channels(s)[0];
+        t = merge([s, m]);
+    "));
+    let sound = |name| match scope.get(name) {
+        Some(Value::Sound(sound)) => sound,
+        _ => panic!("{name} is not a sound"),
+    };
+    let rate = crate::sound::SAMPLE_RATE as usize;
+    let ticks = crate::sound::TICKS;
+    let channels = |name| sound(name).channels().len();
+    assert_eq!(channels("s"), 2);
+    assert_eq!(channels("x"), 2);
+    assert_eq!(channels("y"), 2);
+    assert_eq!(channels("l"), 1);
+    assert_eq!(channels("t"), 3);
+    assert_eq!(sound("s").len(), ticks);
+    assert_eq!(sound("y").len(), ticks * 2);
+    assert_eq!(
+        scope.get("s").unwrap().to_string(),
+        "<sound 1.0s, 2 channels>"
+    );
+    let samples = |name, channel: usize| sound(name).channels()[channel].to_vec(rate as u32);
+    assert!(samples("p", 1).iter().all(|&x| x.abs() < 1e-15));
+    assert_eq!(samples("p", 0), samples("m", 0));
+    let (left, right) = (samples("centre", 0), samples("centre", 1));
+    assert!(left.iter().zip(&right).all(|(l, r)| (l - r).abs() < 1e-12));
+    assert!((left[rate / 1760] - 0.5f64.sqrt()).abs() < 1e-3);
+    assert_eq!(samples("x", 1)[rate / 2..], samples("m", 0)[rate / 2..]);
+    assert!(matches!(
+        run("s = stereo(stereo(sine(1, 1), sine(1, 1)), sine(1, 1));"),
+        Err(Error::InvalidChannels(_, 2))
+    ));
+    assert!(matches!(
+        run("s = merge([]);"),
+        Err(Error::InvalidChannels(_, 0))
+    ));
+    assert!(matches!(
+        run("m = sine(1, 1); s = stereo(m, m) + merge([m, m, m]);"),
+        Err(Error::InvalidOperands(..))
+    ));
+    assert!(matches!(
+        run("m = sine(1, 1); s = pan(merge([m, m, m]), 0);"),
+        Err(Error::InvalidChannels(_, 3))
+    ));
+}
+
+#[test]
+fn sequence() {
+    let scope = ok(run("
+        one = line(1, 1, 0.5);
+        two = line(2, 2, 0.25);
+        s = seq([one, 0.25, two]);
+        grid = seq([two, 0, two, 0], step: 0.5);
+        p = par([sine(440, 1), one]);
+        r = one.repeat(3);
+        l = two.loop(0.6);
+        st = par([stereo(one, one), two]);
+    "));
+    let sound = |name| match scope.get(name) {
+        Some(Value::Sound(sound)) => sound,
+        _ => panic!("{name} is not a sound"),
+    };
+    let rate = crate::sound::SAMPLE_RATE as usize;
+    let ticks = crate::sound::TICKS;
+    let samples = |name| sound(name).channels()[0].to_vec(rate as u32);
+    assert_eq!(sound("s").len(), ticks);
+    let s = samples("s");
+    assert_eq!((s[0], s[rate / 2], s[rate * 3 / 4]), (1., 0., 2.));
+    assert_eq!(sound("grid").len(), ticks * 5 / 4);
+    let grid = samples("grid");
+    assert_eq!((grid[rate / 4], grid[rate]), (0., 2.));
+    assert_eq!(sound("p").len(), ticks);
+    assert_eq!(sound("r").len(), ticks * 3 / 2);
+    assert!(samples("r").iter().all(|&x| x == 1.));
+    assert_eq!(sound("l").len(), ticks * 3 / 5);
+    assert!(samples("l").iter().all(|&x| x == 2.));
+    assert_eq!(sound("st").channels().len(), 2);
+    assert_eq!(sound("st").channels()[1].to_vec(rate as u32)[0], 3.);
+    assert!(matches!(
+        run("s = seq([]);"),
+        Err(Error::InvalidChannels(_, 0))
+    ));
+    assert!(matches!(
+        run("s = seq([\"a\"]);"),
+        Err(Error::InvalidArgument(_, "string"))
+    ));
+    assert!(matches!(
+        run("m = sine(1, 1); s = par([stereo(m, m), merge([m, m, m])]);"),
+        Err(Error::InvalidChannels(_, 3))
+    ));
+    assert!(matches!(
+        run("s = sine(1, 1).repeat(0);"),
+        Err(Error::InvalidArgument(_, "integer"))
+    ));
+    // 時刻が表せないほど長い，または回数が多すぎる
+    assert!(matches!(
+        run("s = seq([1e12, 1e12, sine(440, 1)]);"),
+        Err(Error::TooLongSound(_))
+    ));
+    assert!(matches!(
+        run("s = loop(sine(440, 1), 1e12);"),
+        Err(Error::TooManyRepetitions(..))
+    ));
+    assert!(matches!(
+        run("s = repeat(sine(440, 1e9), 2000000000);"),
+        Err(Error::TooManyRepetitions(_, 2000000000))
+    ));
+    assert!(matches!(
+        run("s = repeat(sine(440, 1e9), 60000);"),
+        Err(Error::TooLongSound(_))
+    ));
+}
+
+#[test]
+fn tempo() {
+    let scope = ok(run("
+        a = beats(2);
+        tempo(90);
+        b = beats(3);
+        c = bars(1);
+        meter(3);
+        d = bars(1, from: 1);
+        tempo(60, at: 6);
+        e = beats(2, from: 5);
+        kick = line(1, 1, 0.1);
+        k = kick >>> beats(2);
+    "));
+    let float = |name| match scope.get(name) {
+        Some(Value::Float(x)) => x,
+        _ => panic!("{name} is not a float"),
+    };
+    assert_eq!(float("a"), 1.);
+    assert_eq!(float("b"), 2.);
+    assert_eq!(float("c"), 8. / 3.);
+    assert_eq!(float("d"), 2.);
+    assert_eq!(float("e"), 2. / 3. + 1.);
+    assert_eq!(
+        scope.get("k").unwrap().to_string(),
+        format!("<sound {:?}s>", 4. / 3. + 0.1)
+    );
+    assert!(matches!(
+        run("tempo(0);"),
+        Err(Error::InvalidArgument(_, "integer"))
+    ));
+    assert!(matches!(
+        run("tempo(120, at: -1);"),
+        Err(Error::InvalidArgument(_, "integer"))
+    ));
+    assert!(matches!(
+        run("tempo(120, at: 4, ramp: 1);"),
+        Err(Error::InvalidArgument(_, "integer"))
+    ));
+}
@@ -0,0 +1,217 @@
+// バイトコードを実行するスタック機械
+
+use std::rc::Rc;
+
+use super::compile::{Code, Instr, Place, PlaceKey};
+use super::{load, lookup, method, operator, store, Flow, Interpreter, Key, Scope};
+use crate::error::Error;
+use crate::value::{self, Value};
+
+impl Interpreter {
+    // 最後に積まれている値を文の値とする
+    pub(super) fn run_code(&mut self, code: &Code, scope: &Rc<Scope>) -> Result<Flow, Error> {
+        let mut stack = Vec::with_capacity(16);
+        // 仮引数を持つ関数の有効範囲と，ブロックの中では内側の有効範囲
+        let frame = scope;
+        let mut scope = scope.clone();
+        let mut pc = 0;
+        while let Some(&instr) = code.instrs.get(pc) {
+            pc += 1;
+            let range = |index: u32| &code.ranges[index as usize];
+            match instr {
+                Instr::Constant(index) => stack.push(code.constants[index as usize].clone()),
+                Instr::Load(name, pos) => {
+                    let value = lookup(&code.names[name as usize], &scope)
+                        .ok_or_else(|| Error::UndefinedVariable(range(pos).clone()))?;
+                    stack.push(value);
+                }
+                Instr::Store(name) => {
+                    scope.assign(&code.names[name as usize], stack.last().unwrap().clone());
+                }
+                Instr::LoadSlot(index) => stack.push(frame.get_slot(index as usize)),
+                Instr::StoreSlot(index) => {
+                    frame.set_slot(index as usize, stack.last().unwrap().clone());
+                }
+                Instr::StorePlace(index) => {
+                    let place = &code.places[index as usize];
+                    let indices = pop_indices(&mut stack, place);
+                    let value = stack.pop().unwrap();
+                    let (pos, name) = &place.variable;
+                    stack.push(store(&scope, (pos, name), path(place, indices), value)?);
+                }
+                Instr::LoadPlace(index) => {
+                    let place = &code.places[index as usize];
+                    let indices = pop_indices(&mut stack, place);
+                    let (pos, name) = &place.variable;
+                    stack.push(load(&scope, (pos, name), &path(place, indices))?);
+                }
+                Instr::Pop => {
+                    stack.pop();
+                }
+                Instr::Dup => stack.push(stack.last().unwrap().clone()),
+                Instr::DupN(n) => {
+                    let top = stack.len() - n as usize;
+                    stack.extend_from_within(top..);
+                }
+                Instr::Swap => {
+                    let len = stack.len();
+                    stack.swap(len - 2, len - 1);
+                }
+                Instr::Rotate(n) => {
+                    let value = stack.pop().unwrap();
+                    stack.insert(stack.len() - n as usize, value);
+                }
+                Instr::UnOp(op, op_pos, operand_pos) => {
+                    let operand = stack.pop().unwrap();
+                    let op = (range(op_pos), op);
+                    stack.push(operator::unary(op, (range(operand_pos), operand))?);
+                }
+                Instr::BinOp(op, op_pos, left_pos, right_pos) => {
+                    let right = stack.pop().unwrap();
+                    let left = stack.pop().unwrap();
+                    let op = (range(op_pos), op);
+                    let value =
+                        operator::binary(op, (range(left_pos), left), (range(right_pos), right))?;
+                    stack.push(value);
+                }
+                Instr::Compare(op, op_pos, left_pos, right_pos) => {
+                    let right = stack.pop().unwrap();
+                    let left = stack.pop().unwrap();
+                    let op = (range(op_pos), op);
+                    let result = operator::binary(
+                        op,
+                        (range(left_pos), left),
+                        (range(right_pos), right.clone()),
+                    )?;
+                    stack.push(right);
+                    stack.push(result);
+                }
+                Instr::LogicalOperand(op_pos, operand_pos) => {
+                    let value = stack.pop().unwrap();
+                    let truth = operator::truth(&value).ok_or_else(|| {
+                        Error::InvalidOperand(
+                            range(op_pos).clone(),
+                            range(operand_pos).clone(),
+                            value.type_name(),
+                        )
+                    })?;
+                    stack.push(Value::Boolean(truth));
+                }
+                Instr::Condition(pos) => {
+                    let value = stack.pop().unwrap();
+                    let truth = operator::truth(&value).ok_or_else(|| {
+                        Error::InvalidCondition(range(pos).clone(), value.type_name())
+                    })?;
+                    stack.push(Value::Boolean(truth));
+                }
+                Instr::JumpIfFalse(target) | Instr::JumpIfTrue(target) => {
+                    let jump = matches!(instr, Instr::JumpIfTrue(_));
+                    if let Some(Value::Boolean(value)) = stack.pop() {
+                        if value == jump {
+                            pc = target as usize;
+                        }
+                    }
+                }
+                Instr::Jump(target) => pc = target as usize,
+                Instr::Call(index) => {
+                    let call = &code.calls[index as usize];
+                    let named_values = stack.split_off(stack.len() - call.named_args.len());
+                    let values = stack.split_off(stack.len() - call.args.len());
+                    let fnc = stack.pop().unwrap();
+                    let (fnc, receiver) = match &call.method {
+                        Some((object_pos, name)) => method((object_pos, fnc), name, &scope)?,
+                        None => (fnc, None),
+                    };
+                    let value = match fnc {
+                        // 位置による引数だけで，残りの仮引数に既定値があれば位置を付けずに渡す
+                        Value::Function(function)
+                            if receiver.is_none()
+                                && named_values.is_empty()
+                                && function.definition.params.get(values.len()..).is_some_and(
+                                    |rest| rest.iter().all(|param| param.default.is_some()),
+                                ) =>
+                        {
+                            self.call_values(&call.pos, &function, values)?
+                        }
+                        fnc => {
+                            let args = receiver
+                                .into_iter()
+                                .chain(call.args.iter().cloned().zip(values))
+                                .collect();
+                            let named_args = call
+                                .named_args
+                                .iter()
+                                .zip(named_values)
+                                .map(|((name, pos), value)| (name.clone(), (pos.clone(), value)))
+                                .collect();
+                            self.call_value(&call.pos, (&call.fnc, fnc), args, named_args)?
+                        }
+                    };
+                    stack.push(value);
+                }
+                Instr::List(len) => {
+                    let elems = stack.split_off(stack.len() - len as usize);
+                    stack.push(Value::List(Rc::new(elems)));
+                }
+                Instr::Record(index) => {
+                    let names = &code.records[index as usize];
+                    let values = stack.split_off(stack.len() - names.len());
+                    let fields = names.iter().cloned().zip(values).collect();
+                    stack.push(Value::Record(Rc::new(value::Record { fields })));
+                }
+                Instr::Index(container_pos, index_pos) => {
+                    let index = stack.pop().unwrap();
+                    let container = stack.pop().unwrap();
+                    let value = operator::index(
+                        (range(container_pos), container),
+                        (range(index_pos), index),
+                    )?;
+                    stack.push(value);
+                }
+                Instr::Member(index) => {
+                    let object = stack.pop().unwrap();
+                    stack.push(operator::member(object, &code.members[index as usize])?);
+                }
+                Instr::EnterScope => scope = Scope::new(Some(scope)),
+                Instr::LeaveScope => scope = scope.parent().unwrap().clone(),
+                Instr::Import(index) => {
+                    let (pos, path, name) = &code.imports[index as usize];
+                    self.import_as(pos, path, name.as_ref(), &scope)?;
+                }
+                Instr::Function(index) => {
+                    let function = &code.functions[index as usize];
+                    let value = value::Function {
+                        definition: function.definition.clone(),
+                        scope: scope.clone(),
+                        code: Some(function.clone()),
+                    };
+                    scope.define(&function.definition.name.1, Value::Function(value.into()));
+                }
+                Instr::Return(pos) => {
+                    return Ok(Flow::Return(range(pos).clone(), stack.pop().unwrap()));
+                }
+            }
+        }
+        Ok(Flow::Next(stack.pop().unwrap_or(Value::Void)))
+    }
+}
+
+// 代入先の添字を取り除く（評価した順に並べる）
+fn pop_indices(stack: &mut Vec<Value>, place: &Place) -> Vec<Value> {
+    stack.split_off(stack.len() - place.indices())
+}
+
+// 変数からたどる添字やフィールドの並び
+fn path(place: &Place, indices: Vec<Value>) -> Vec<Key<'_>> {
+    let mut indices = indices.into_iter();
+    place
+        .keys
+        .iter()
+        .map(|key| match key {
+            PlaceKey::Index(container_pos, index_pos) => {
+                Key::Index(container_pos, (index_pos, indices.next().unwrap()))
+            }
+            PlaceKey::Member(name) => Key::Member(name),
+        })
+        .collect()
+}
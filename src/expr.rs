@@ -1,19 +1,25 @@
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum UnOp {
     Plus,
     Minus,
     Recip,
     LogicalNot,
     BitNot,
+    // `++x`, `--x`, `x++`, `x--`（被演算子に代入する）
+    PreIncrement,
+    PreDecrement,
+    PostIncrement,
+    PostDecrement,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum BinOp {
     Add,
     Sub,
     Mul,
     Div,
     Rem,
+    Pow,
     LeftShift,
     RightShift,
     ForwardShift,
@@ -35,6 +41,7 @@ pub enum BinOp {
     MulAssign,
     DivAssign,
     RemAssign,
+    PowAssign,
     BitAndAssign,
     BitOrAssign,
     BitXorAssign,
@@ -44,16 +51,52 @@ pub enum BinOp {
     BackwardShiftAssign,
 }
 
+impl BinOp {
+    // 複合代入演算子なら，対応する二項演算子
+    pub fn compound(&self) -> Option<BinOp> {
+        match self {
+            BinOp::AddAssign => Some(BinOp::Add),
+            BinOp::SubAssign => Some(BinOp::Sub),
+            BinOp::MulAssign => Some(BinOp::Mul),
+            BinOp::DivAssign => Some(BinOp::Div),
+            BinOp::RemAssign => Some(BinOp::Rem),
+            BinOp::PowAssign => Some(BinOp::Pow),
+            BinOp::BitAndAssign => Some(BinOp::BitAnd),
+            BinOp::BitOrAssign => Some(BinOp::BitOr),
+            BinOp::BitXorAssign => Some(BinOp::BitXor),
+            BinOp::LeftShiftAssign => Some(BinOp::LeftShift),
+            BinOp::RightShiftAssign => Some(BinOp::RightShift),
+            BinOp::ForwardShiftAssign => Some(BinOp::ForwardShift),
+            BinOp::BackwardShiftAssign => Some(BinOp::BackwardShift),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Expr {
     Identifier(String),
     Integer(i32),
     Float(f64),
     String(String),
+    Boolean(bool),
     UnOp(PUnOp, Box<PExpr>),
     BinOp(PBinOp, Box<PExpr>, Box<PExpr>),
     Group(Box<PExpr>),
-    Call(Box<PExpr>, Vec<PExpr>),
+    // `cond ? a : b`
+    Conditional(Box<PExpr>, Box<PExpr>, Box<PExpr>),
+    // 2 つ以上の比較演算子を続けた `a < b <= c`
+    Comparison(Box<PExpr>, Vec<(PBinOp, PExpr)>),
+    // 関数と，位置による引数と，名前付きの引数
+    Call(Box<PExpr>, Vec<PExpr>, Vec<PNamedArg>),
+    // `[a, b, c]`
+    List(Vec<PExpr>),
+    // `xs[i]`
+    Index(Box<PExpr>, Box<PExpr>),
+    // `a.b`
+    Member(Box<PExpr>, PName),
+    // `{ name: value, ... }`
+    Record(Vec<PNamedArg>),
 }
 
 #[derive(Debug)]
@@ -65,10 +108,17 @@ pub enum PreExpr {
     HexInt(String),
     Float(String),
     String(String),
+    Boolean(bool),
     UnOp(PUnOp, Box<PPreExpr>),
     BinOp(PBinOp, Box<PPreExpr>, Box<PPreExpr>),
     Group(Box<PPreExpr>),
-    Call(Box<PPreExpr>, Vec<PPreExpr>),
+    Conditional(Box<PPreExpr>, Box<PPreExpr>, Box<PPreExpr>),
+    Comparison(Box<PPreExpr>, Vec<(PBinOp, PPreExpr)>),
+    Call(Box<PPreExpr>, Vec<PPreExpr>, Vec<PPreNamedArg>),
+    List(Vec<PPreExpr>),
+    Index(Box<PPreExpr>, Box<PPreExpr>),
+    Member(Box<PPreExpr>, PName),
+    Record(Vec<PPreNamedArg>),
 }
 
 impl From<PreExpr> for Expr {
@@ -81,6 +131,7 @@ impl From<PreExpr> for Expr {
             PreExpr::HexInt(s) => Expr::Integer(i32::from_str_radix(&s, 16).unwrap()),
             PreExpr::Float(s) => Expr::Float(s.parse().unwrap()),
             PreExpr::String(s) => Expr::String(s),
+            PreExpr::Boolean(value) => Expr::Boolean(value),
             PreExpr::UnOp(op, operand) => {
                 if matches!(op.1, UnOp::Minus) {
                     match operand.1 {
@@ -113,14 +164,53 @@ impl From<PreExpr> for Expr {
                 let expr = (expr.0, expr.1.into());
                 Expr::Group(expr.into())
             }
-            PreExpr::Call(fnc, args) => {
+            PreExpr::Conditional(cond, then, otherwise) => {
+                let cond = (cond.0, cond.1.into());
+                let then = (then.0, then.1.into());
+                let otherwise = (otherwise.0, otherwise.1.into());
+                Expr::Conditional(cond.into(), then.into(), otherwise.into())
+            }
+            PreExpr::Comparison(first, rest) => {
+                let first = (first.0, first.1.into());
+                let rest = rest
+                    .into_iter()
+                    .map(|(op, (pos, operand))| (op, (pos, operand.into())))
+                    .collect();
+                Expr::Comparison(first.into(), rest)
+            }
+            PreExpr::Call(fnc, args, named_args) => {
                 let fnc = (fnc.0, fnc.1.into());
                 let args = args
                     .into_iter()
                     .map(|(pos, arg)| (pos, arg.into()))
                     .collect();
-                Expr::Call(fnc.into(), args)
+                let named_args = named_args
+                    .into_iter()
+                    .map(|(name, (pos, arg))| (name, (pos, arg.into())))
+                    .collect();
+                Expr::Call(fnc.into(), args, named_args)
             }
+            PreExpr::List(elems) => Expr::List(
+                elems
+                    .into_iter()
+                    .map(|(pos, elem)| (pos, elem.into()))
+                    .collect(),
+            ),
+            PreExpr::Index(container, index) => {
+                let container = (container.0, container.1.into());
+                let index = (index.0, index.1.into());
+                Expr::Index(container.into(), index.into())
+            }
+            PreExpr::Member(object, name) => {
+                let object = (object.0, object.1.into());
+                Expr::Member(object.into(), name)
+            }
+            PreExpr::Record(fields) => Expr::Record(
+                fields
+                    .into_iter()
+                    .map(|(name, (pos, value))| (name, (pos, value.into())))
+                    .collect(),
+            ),
         }
     }
 }
@@ -130,3 +220,6 @@ pub type PBinOp = (pos::Range, BinOp);
 pub type PUnOp = (pos::Range, UnOp);
 pub type PPreExpr = (pos::Range, PreExpr);
 pub type PExpr = (pos::Range, Expr);
+pub type PName = (pos::Range, String);
+pub type PPreNamedArg = (PName, PPreExpr);
+pub type PNamedArg = (PName, PExpr);
@@ -0,0 +1,165 @@
+// 定数の畳み込みと構文木の簡約
+//
+// 置き換えた式は元の式の位置を持つので，誤りの位置は変わらない
+
+use std::rc::Rc;
+
+use crate::eval::operator;
+use crate::expr::{BinOp, Expr, PExpr, UnOp};
+use crate::sentence::{self, PSentence, Sentence};
+use crate::value::Value;
+
+mod test;
+
+pub fn sentence((pos, sentence): PSentence) -> PSentence {
+    let sentence = match sentence {
+        Sentence::Expr(expr) => Sentence::Expr(expr.map(self::expr)),
+        Sentence::Return(expr) => Sentence::Return(expr.map(self::expr)),
+        Sentence::Import(..) => sentence,
+        Sentence::Block(sentences) => {
+            Sentence::Block(sentences.into_iter().map(self::sentence).collect())
+        }
+        Sentence::Function(function) => match Rc::try_unwrap(function) {
+            Ok(function) => Sentence::Function(Rc::new(sentence::Function {
+                name: function.name,
+                params: function
+                    .params
+                    .into_iter()
+                    .map(|param| sentence::Parameter {
+                        name: param.name,
+                        default: param.default.map(self::expr),
+                    })
+                    .collect(),
+                body: function.body.into_iter().map(self::sentence).collect(),
+            })),
+            // 既に共有されていれば手を付けない
+            Err(function) => Sentence::Function(function),
+        },
+    };
+    (pos, sentence)
+}
+
+pub fn expr((pos, expr): PExpr) -> PExpr {
+    let boxed = |expr: Box<PExpr>| Box::new(self::expr(*expr));
+    let expr = match expr {
+        Expr::Identifier(_)
+        | Expr::Integer(_)
+        | Expr::Float(_)
+        | Expr::String(_)
+        | Expr::Boolean(_) => expr,
+        // 括弧は外し，位置は括弧を含めたものにする
+        Expr::Group(inner) => return (pos, self::expr(*inner).1),
+        Expr::UnOp(op, operand) => {
+            let operand = self::expr(*operand);
+            match (op.1, &operand.1) {
+                // 数に付いた `+` と重なった `+` は取り除く
+                (
+                    UnOp::Plus,
+                    Expr::Integer(_) | Expr::Float(_) | Expr::UnOp((_, UnOp::Plus), _),
+                ) => return (pos, operand.1),
+                _ => match constant(&operand.1) {
+                    Some(value) if is_pure(op.1) => {
+                        match operator::unary((&op.0, op.1), (&operand.0, value))
+                            .ok()
+                            .and_then(literal)
+                        {
+                            Some(expr) => expr,
+                            None => Expr::UnOp(op, operand.into()),
+                        }
+                    }
+                    _ => Expr::UnOp(op, operand.into()),
+                },
+            }
+        }
+        Expr::BinOp(op, left, right) => {
+            let (left, right) = (self::expr(*left), self::expr(*right));
+            let folded = match (constant(&left.1), constant(&right.1)) {
+                (Some(l), Some(r)) if op.1 != BinOp::Assign && op.1.compound().is_none() => {
+                    // 0 で割るなど誤りになるものは実行するときに報告する
+                    operator::binary((&op.0, op.1), (&left.0, l), (&right.0, r))
+                        .ok()
+                        .and_then(literal)
+                }
+                _ => None,
+            };
+            match folded {
+                Some(expr) => expr,
+                None => Expr::BinOp(op, left.into(), right.into()),
+            }
+        }
+        Expr::Conditional(cond, then, otherwise) => {
+            let cond = self::expr(*cond);
+            match constant(&cond.1).as_ref().and_then(operator::truth) {
+                Some(true) => return (pos, self::expr(*then).1),
+                Some(false) => return (pos, self::expr(*otherwise).1),
+                None => Expr::Conditional(cond.into(), boxed(then), boxed(otherwise)),
+            }
+        }
+        Expr::Comparison(first, rest) => Expr::Comparison(
+            boxed(first),
+            rest.into_iter()
+                .map(|(op, operand)| (op, self::expr(operand)))
+                .collect(),
+        ),
+        Expr::Call(fnc, args, named_args) => Expr::Call(
+            Box::new(callee(*fnc)),
+            args.into_iter().map(self::expr).collect(),
+            named_args
+                .into_iter()
+                .map(|(name, arg)| (name, self::expr(arg)))
+                .collect(),
+        ),
+        Expr::List(elems) => Expr::List(elems.into_iter().map(self::expr).collect()),
+        Expr::Index(container, index) => Expr::Index(boxed(container), boxed(index)),
+        Expr::Member(object, name) => Expr::Member(boxed(object), name),
+        Expr::Record(fields) => Expr::Record(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name, self::expr(value)))
+                .collect(),
+        ),
+    };
+    (pos, expr)
+}
+
+// 呼び出す関数の式
+//
+// `(a.f)(...)` の括弧を外すと `a.f(...)` というメソッド呼び出しになってしまうので，
+// フィールドを取り出す式を囲む括弧は残す
+fn callee((pos, fnc): PExpr) -> PExpr {
+    match fnc {
+        Expr::Group(inner) => match self::expr(*inner) {
+            member @ (_, Expr::Member(..)) => (pos, Expr::Group(Box::new(member))),
+            (_, inner) => (pos, inner),
+        },
+        fnc => self::expr((pos, fnc)),
+    }
+}
+
+// 代入を伴う演算子は畳み込まない
+fn is_pure(op: UnOp) -> bool {
+    !matches!(
+        op,
+        UnOp::PreIncrement | UnOp::PreDecrement | UnOp::PostIncrement | UnOp::PostDecrement
+    )
+}
+
+fn constant(expr: &Expr) -> Option<Value> {
+    match *expr {
+        Expr::Integer(n) => Some(Value::Integer(n)),
+        Expr::Float(x) => Some(Value::Float(x)),
+        Expr::Boolean(value) => Some(Value::Boolean(value)),
+        Expr::String(ref s) => Some(Value::String(s.as_str().into())),
+        _ => None,
+    }
+}
+
+fn literal(value: Value) -> Option<Expr> {
+    match value {
+        Value::Integer(n) => Some(Expr::Integer(n)),
+        Value::Float(x) => Some(Expr::Float(x)),
+        Value::Boolean(value) => Some(Expr::Boolean(value)),
+        Value::String(s) => Some(Expr::String(s.to_string())),
+        _ => None,
+    }
+}
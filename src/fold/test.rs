@@ -0,0 +1,65 @@
+#![cfg(test)]
+
+use crate::expr::{BinOp, Expr, PExpr};
+use crate::lexer::Lexer;
+use crate::parser;
+use crate::sentence::Sentence;
+
+fn fold(input: &str) -> PExpr {
+    let mut lexer = Lexer::from_str("test", input);
+    let (pos, sentence) = parser::parse_sentence(&mut lexer).ok().flatten().unwrap();
+    match super::sentence((pos, sentence.into())).1 {
+        Sentence::Expr(Some(expr)) => expr,
+        _ => panic!("{input} is not an expression"),
+    }
+}
+
+#[test]
+fn constant() {
+    let (pos, expr) = fold("(2 * 3) + +4;");
+    assert_eq!(format!("{pos:?}"), "0:0-0:12");
+    assert!(matches!(expr, Expr::Integer(10)));
+    assert!(matches!(fold("2 ** -1 < 1 ? \"a\" + \"b\" : 0;").1, Expr::String(s) if s == "ab"));
+    assert!(matches!(fold("!(1.5 > 2);").1, Expr::Boolean(true)));
+}
+
+#[test]
+fn simplify() {
+    let (_, expr) = fold("x = ((y)) * +(1 + 1);");
+    let Expr::BinOp((_, BinOp::Assign), _, right) = expr else {
+        panic!();
+    };
+    let Expr::BinOp((_, BinOp::Mul), left, right) = right.1 else {
+        panic!();
+    };
+    assert!(matches!(&left.1, Expr::Identifier(y) if y == "y"));
+    assert_eq!(format!("{:?}", left.0), "0:4-0:9");
+    assert!(matches!(right.1, Expr::Integer(2)));
+    assert_eq!(format!("{:?}", right.0), "0:12-0:20");
+}
+
+#[test]
+fn keep_errors() {
+    // 実行するときの誤りになる式は畳み込まない
+    let (_, expr) = fold("1 / (2 - 2);");
+    let Expr::BinOp((_, BinOp::Div), _, divisor) = expr else {
+        panic!();
+    };
+    assert!(matches!(divisor.1, Expr::Integer(0)));
+    assert_eq!(format!("{:?}", divisor.0), "0:4-0:11");
+    assert!(matches!(fold("+\"a\";").1, Expr::UnOp(..)));
+    assert!(matches!(fold("x++;").1, Expr::UnOp(..)));
+}
+
+// 括弧で囲んだフィールドを呼ぶ式はメソッド呼び出しにしない
+#[test]
+fn grouped_callee() {
+    let Expr::Call(fnc, ..) = fold("((xs.len))();").1 else {
+        panic!();
+    };
+    assert!(matches!(&fnc.1, Expr::Group(member) if matches!(member.1, Expr::Member(..))));
+    let Expr::Call(fnc, ..) = fold("(f)();").1 else {
+        panic!();
+    };
+    assert!(matches!(&fnc.1, Expr::Identifier(f) if f == "f"));
+}
@@ -1,12 +1,129 @@
 use crate::error::Error;
 use crate::pos;
 use crate::token::{PToken, Token};
+use std::cell::Cell;
 use std::collections::VecDeque;
 use std::mem;
+use std::rc::Rc;
+use unicode_xid::UnicodeXID;
+
+pub fn keyword(s: &str) -> Option<Token> {
+    match s {
+        "if" => Some(Token::KeywordIf),
+        "for" => Some(Token::KeywordFor),
+        "else" => Some(Token::KeywordElse),
+        "import" => Some(Token::KeywordImport),
+        "as" => Some(Token::KeywordAs),
+        "return" => Some(Token::KeywordReturn),
+        "true" => Some(Token::KeywordTrue),
+        "false" => Some(Token::KeywordFalse),
+        _ => None,
+    }
+}
+
+// 識別子の始めと続きに使える文字
+pub fn is_identifier_start(ch: char) -> bool {
+    ch.is_xid_start() || matches!(ch, '_' | '$')
+}
+pub fn is_identifier_continue(ch: char) -> bool {
+    ch.is_xid_continue() || ch == '$'
+}
 
 pub struct LineLexer {
     comment: Vec<pos::Start>,
-    string: Option<(pos::Start, String)>,
+    string: Option<StringLiteral>,
+}
+
+// 複数行にわたりうる文字列リテラルの読みかけの状態
+//
+// `"..."` は改行をそのまま含む（`\r\n` は `\n` になる）．
+// `"""..."""` は 2 行目以降に共通する字下げを取り除き，
+// 開きの `"""` 直後の改行と，閉じの `"""` の前の空白だけの行を含まない．
+// どちらも行末の `\` は改行と次の行の先頭の空白を読み飛ばす．
+struct StringLiteral {
+    start: pos::Start,
+    triple: bool,
+    // 読み終えた行と，その字下げの文字数（空白だけの行なら None）
+    lines: Vec<(Option<usize>, String)>,
+    current: String,
+    // 今の行にまだ空白しか現れていないか
+    blank: bool,
+    indent: usize,
+    // 行末の `\` の後，次の行の先頭の空白を読み飛ばしている
+    continued: bool,
+}
+
+impl StringLiteral {
+    fn new(start: pos::Start, triple: bool) -> StringLiteral {
+        StringLiteral {
+            start,
+            triple,
+            lines: Vec::new(),
+            current: String::new(),
+            blank: true,
+            indent: 0,
+            continued: false,
+        }
+    }
+    fn push(&mut self, ch: char) {
+        if self.continued && ch.is_whitespace() {
+            return;
+        }
+        self.continued = false;
+        if self.blank && matches!(ch, ' ' | '\t') {
+            self.indent += 1;
+        } else {
+            self.blank = false;
+        }
+        self.current.push(ch);
+    }
+    fn push_escaped(&mut self, ch: char) {
+        self.continued = false;
+        self.blank = false;
+        self.current.push(ch);
+    }
+    fn break_line(&mut self) {
+        if self.continued {
+            // 行末の `\` に続く空白だけの行も読み飛ばす
+            return;
+        }
+        let indent = (!self.blank).then_some(self.indent);
+        self.lines.push((indent, mem::take(&mut self.current)));
+        self.blank = true;
+        self.indent = 0;
+    }
+    fn continue_line(&mut self) {
+        self.continued = true;
+    }
+    fn finish(mut self) -> String {
+        self.continued = false;
+        self.break_line();
+        let mut lines = self.lines;
+        if self.triple && lines.len() > 1 {
+            let min_indent = lines[1..]
+                .iter()
+                .filter_map(|&(indent, _)| indent)
+                .min()
+                .unwrap_or(0);
+            for (indent, line) in &mut lines[1..] {
+                *line = match indent {
+                    Some(_) => line.chars().skip(min_indent).collect(),
+                    None => String::new(),
+                };
+            }
+            if lines.last().unwrap().0.is_none() {
+                lines.pop();
+            }
+            if lines[0].1.is_empty() {
+                lines.remove(0);
+            }
+        }
+        lines
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
 }
 
 impl LineLexer {
@@ -20,8 +137,8 @@ impl LineLexer {
     pub fn deal_with_eof(&mut self) -> Result<(), Error> {
         if !self.comment.is_empty() {
             Err(Error::UnterminatedComment(mem::take(&mut self.comment)))
-        } else if let Some((start, _)) = self.string.take() {
-            Err(Error::UnterminatedStringLiteral(start))
+        } else if let Some(literal) = self.string.take() {
+            Err(Error::UnterminatedStringLiteral(literal.start))
         } else {
             Ok(())
         }
@@ -29,49 +146,87 @@ impl LineLexer {
 
     pub fn run(
         &mut self,
+        source: &Rc<pos::Source>,
         line_num: usize,
         line: &str,
         tokens: &mut VecDeque<PToken>,
     ) -> Result<(), Error> {
+        // 位置はバイト単位ではなく文字単位の列で表す
+        //
+        // 列は前に求めた位置から数え進める（行の始めから数え直すと長い行で遅くなる）
+        let last = Cell::new((0, 0));
+        let column = |index: usize| {
+            let (last_index, last_column) = last.get();
+            let column = if last_index <= index {
+                last_column + line[last_index..index].chars().count()
+            } else {
+                line[..index].chars().count()
+            };
+            last.set((index, column));
+            column
+        };
         let mut iter = line.char_indices().peekable();
         while let Some((index, ch)) = iter.next() {
             let second_is = |ch1| move |&(_, ch2): &_| ch1 == ch2;
             let range_gen = |peeked: Option<_>| {
-                pos::Range::new_single_line(line_num, index, peeked.map(|&(i, _)| i))
+                pos::Range::new_single_line(
+                    source.clone(),
+                    line_num,
+                    column(index),
+                    peeked.map(|&(i, _)| column(i)),
+                )
             };
             if !self.comment.is_empty() {
                 if ch == '*' && iter.next_if(second_is('/')).is_some() {
                     self.comment.pop();
                 } else if ch == '/' && iter.next_if(second_is('*')).is_some() {
-                    self.comment.push(pos::Start::new(line_num, index));
+                    self.comment
+                        .push(pos::Start::new(source.clone(), line_num, column(index)));
                 }
-            } else if ch == '"' {
-                if let Some((start, string)) = self.string.take() {
-                    let end = pos::End::new(line_num, iter.peek().map(|&(i, _)| i));
+            } else if let Some(literal) = &mut self.string {
+                if ch == '"' && (!literal.triple || line[index..].starts_with("\"\"\"")) {
+                    if literal.triple {
+                        iter.nth(1);
+                    }
+                    let literal = self.string.take().unwrap();
+                    let start = literal.start.clone();
+                    let end = pos::End::new(line_num, iter.peek().map(|&(i, _)| column(i)));
                     let range = pos::Range::new(start, end);
-                    tokens.push_back((range, Token::String(string)));
-                } else {
-                    let start = pos::Start::new(line_num, index);
-                    self.string = Some((start, String::new()))
-                }
-            } else if let Some((start, string)) = &mut self.string {
-                string.push(match ch {
-                    '\\' => match iter.next() {
+                    tokens.push_back((range, Token::String(literal.finish())));
+                } else if ch == '\\' {
+                    match iter.next() {
                         Some((_, ch2)) => match ch2 {
+                            // 行末の `\` は改行を含めない
+                            '\n' => literal.continue_line(),
+                            '\r' if iter.next_if(second_is('\n')).is_some() => {
+                                literal.continue_line()
+                            }
                             // エスケープ
-                            'n' => '\n',
-                            'r' => '\r',
-                            't' => '\t',
-                            '0' => '\0',
+                            'n' => literal.push_escaped('\n'),
+                            'r' => literal.push_escaped('\r'),
+                            't' => literal.push_escaped('\t'),
+                            '0' => literal.push_escaped('\0'),
                             // バックスラッシュの直後の文字を push
                             // `"` や `'` のエスケープを含む
-                            _ => ch2,
+                            _ => literal.push_escaped(ch2),
                         },
-                        None => return Err(Error::UnterminatedStringLiteral(start.clone())),
-                    },
-                    _ => ch,
-                });
-            } else if !ch.is_ascii_whitespace() {
+                        None => {
+                            return Err(Error::UnterminatedStringLiteral(literal.start.clone()))
+                        }
+                    }
+                } else if ch == '\n' || ch == '\r' && iter.next_if(second_is('\n')).is_some() {
+                    literal.break_line();
+                } else {
+                    literal.push(ch);
+                }
+            } else if ch == '"' {
+                let triple = line[index..].starts_with("\"\"\"");
+                if triple {
+                    iter.nth(1);
+                }
+                let start = pos::Start::new(source.clone(), line_num, column(index));
+                self.string = Some(StringLiteral::new(start, triple));
+            } else if !ch.is_whitespace() {
                 // rename ch -> first_ch
                 let first_ch = ch;
                 #[allow(unused_variables)]
@@ -81,25 +236,16 @@ impl LineLexer {
                 #[allow(unused_variables)]
                 let index: ();
                 let token = match first_ch {
-                    'a'..='z' | 'A'..='Z' | '_' | '$' => {
+                    _ if is_identifier_start(first_ch) => {
                         while iter
-                            .next_if(|&(_, ch)| {
-                                matches!(ch,
-                                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '$'
-                                )
-                            })
+                            .next_if(|&(_, ch)| is_identifier_continue(ch))
                             .is_some()
                         {}
                         let s = match iter.peek() {
                             Some(&(index, _)) => &line[first_index..index],
                             None => &line[first_index..],
                         };
-                        match s {
-                            "if" => Token::KeywordIf,
-                            "for" => Token::KeywordFor,
-                            "else" => Token::KeywordElse,
-                            _ => Token::Identifier(s.to_owned()),
-                        }
+                        keyword(s).unwrap_or_else(|| Token::Identifier(s.to_owned()))
                     }
                     '0'..='9' | '.' => {
                         enum State {
@@ -193,7 +339,13 @@ impl LineLexer {
                         }
                     }
                     '*' => {
-                        if iter.next_if(second_is('=')).is_some() {
+                        if iter.next_if(second_is('*')).is_some() {
+                            if iter.next_if(second_is('=')).is_some() {
+                                Token::DoubleAsteriskEqual
+                            } else {
+                                Token::DoubleAsterisk
+                            }
+                        } else if iter.next_if(second_is('=')).is_some() {
                             Token::AsteriskEqual
                         } else {
                             Token::Asterisk
@@ -203,7 +355,11 @@ impl LineLexer {
                         if iter.next_if(second_is('/')).is_some() {
                             return Ok(());
                         } else if iter.next_if(second_is('*')).is_some() {
-                            self.comment.push(pos::Start::new(line_num, first_index));
+                            self.comment.push(pos::Start::new(
+                                source.clone(),
+                                line_num,
+                                column(first_index),
+                            ));
                             continue;
                         } else if iter.next_if(second_is('=')).is_some() {
                             Token::SlashEqual
@@ -309,8 +465,9 @@ impl LineLexer {
                     '}' => Token::ClosingBrace,
                     _ => {
                         return Err(Error::UnexpectedCharacter(pos::Start::new(
+                            source.clone(),
                             line_num,
-                            first_index,
+                            column(first_index),
                         )))
                     }
                 };
@@ -5,6 +5,8 @@ mod test;
 
 use std::collections::VecDeque;
 use std::io::BufRead;
+use std::path::Path;
+use std::rc::Rc;
 
 use crate::error::Error;
 use crate::pos;
@@ -15,27 +17,48 @@ pub struct Lexer {
     tokens: VecDeque<PToken>,
     line_lexer: LineLexer,
     eof: bool,
-    log: Vec<String>,
+    source: Rc<pos::Source>,
     prompt: bool,
 }
 
+// 文字列全体が 1 つの識別子として読めるか（キーワードは除く）
+pub fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(line_lexer::is_identifier_start)
+        && chars.all(line_lexer::is_identifier_continue)
+        && line_lexer::keyword(s).is_none()
+}
+
 impl Lexer {
-    pub fn new(reader: Box<dyn BufRead>, prompt: bool) -> Lexer {
+    pub fn new(reader: Box<dyn BufRead>, source: Rc<pos::Source>, prompt: bool) -> Lexer {
         Lexer {
             reader,
             prompt,
             tokens: VecDeque::new(),
             line_lexer: LineLexer::new(),
             eof: false,
-            log: Vec::new(),
+            source,
         }
     }
-    pub fn log(&self) -> &[String] {
-        &self.log
+    pub fn from_str(name: &str, text: &str) -> Lexer {
+        let reader = std::io::Cursor::new(text.to_owned());
+        Lexer::new(Box::new(reader), pos::Source::new(name), false)
+    }
+    pub fn from_path(path: impl AsRef<Path>) -> std::io::Result<Lexer> {
+        let path = path.as_ref();
+        let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+        Ok(Lexer::new(
+            Box::new(reader),
+            pos::Source::new(path.display().to_string()),
+            false,
+        ))
     }
+    // 読んだ行の残りのトークンを捨てる（対話環境でエラーから立ち直るのに使う）
+    pub fn discard_line(&mut self) {
+        self.tokens.clear();
+    }
+    // 読んだ行のトークンを後ろに足す
     fn read(&mut self) -> Result<(), Error> {
-        assert!(self.tokens.is_empty());
-
         let mut line = String::new();
         if self.prompt {
             // 対話環境ではプロンプトを出す
@@ -52,51 +75,38 @@ impl Lexer {
         if self.eof {
             self.line_lexer.deal_with_eof()
         } else {
-            let result = self.line_lexer.run(self.log.len(), &line, &mut self.tokens);
-            self.log.push(line);
-            result
+            let line_num = self.source.log().len();
+            self.source.push_line(line);
+            let line = &self.source.log()[line_num];
+            self.line_lexer
+                .run(&self.source, line_num, line, &mut self.tokens)
         }
     }
     pub fn next(&mut self) -> Result<Option<PToken>, Error> {
-        loop {
-            if self.eof {
-                return Ok(None);
-            } else if let Some(next) = self.tokens.pop_front() {
-                return Ok(Some(next));
-            }
-            self.read()?;
-        }
+        self.peek(0)?;
+        Ok(self.tokens.pop_front())
     }
     pub fn next_if(&mut self, cond: impl FnOnce(&Token) -> bool) -> Result<Option<PToken>, Error> {
-        loop {
-            if self.eof {
-                return Ok(None);
-            } else if let Some((_, token)) = self.tokens.front() {
-                if cond(token) {
-                    return Ok(self.tokens.pop_front());
-                } else {
-                    return Ok(None);
-                }
-            }
+        match self.peek(0)? {
+            Some(token) if cond(token) => Ok(self.tokens.pop_front()),
+            _ => Ok(None),
+        }
+    }
+    // n 個先（0 なら次）のトークンを，読み進めずに見る
+    pub fn peek(&mut self, n: usize) -> Result<Option<&Token>, Error> {
+        while self.tokens.len() <= n && !self.eof {
             self.read()?;
         }
+        Ok(self.tokens.get(n).map(|(_, token)| token))
     }
     pub fn next_if_map<B>(
         &mut self,
         f: impl FnOnce(&Token) -> Option<B>,
     ) -> Result<Option<(pos::Range, B)>, Error> {
-        loop {
-            if self.eof {
-                return Ok(None);
-            } else if let Some(next) = self.tokens.pop_front() {
-                if let Some(result) = f(&next.1) {
-                    return Ok(Some((next.0, result)));
-                } else {
-                    self.tokens.push_front(next);
-                    return Ok(None);
-                }
-            }
-            self.read()?;
-        }
+        let Some(result) = self.peek(0)?.and_then(f) else {
+            return Ok(None);
+        };
+        let (pos, _) = self.tokens.pop_front().unwrap();
+        Ok(Some((pos, result)))
     }
 }
@@ -1,17 +1,18 @@
 #![cfg(test)]
 
 use super::Lexer;
+use crate::error::Error;
 use crate::token::Token;
 
 #[test]
 fn identifier() {
-    let input: &[_] = b"abc a00 _0123456789$";
-    let mut lexer = Lexer::new(Box::new(input), false);
+    let input = "abc a00 _0123456789$";
+    let mut lexer = Lexer::from_str("test", input);
     for &ans in &["abc", "a00", "_0123456789$"] {
         let next = match lexer.next() {
             Ok(item) => item,
             Err(err) => {
-                err.eprint(&lexer.log);
+                err.eprint();
                 panic!();
             }
         };
@@ -22,3 +23,131 @@ fn identifier() {
         }
     }
 }
+
+fn string_literals(input: &str) -> Vec<(String, String)> {
+    let mut lexer = Lexer::from_str("test", input);
+    let mut ret = Vec::new();
+    loop {
+        match lexer.next() {
+            Ok(Some((range, Token::String(s)))) => ret.push((format!("{range:?}"), s)),
+            Ok(Some(_)) => panic!("not a string literal"),
+            Ok(None) => return ret,
+            Err(err) => {
+                err.eprint();
+                panic!();
+            }
+        }
+    }
+}
+
+#[test]
+fn multi_line_string() {
+    let input = "\"abc\r\n  def\n\" \"x\\\n    y\"";
+    assert_eq!(
+        string_literals(input),
+        [
+            ("0:0-2:1".to_string(), "abc\n  def\n".to_string()),
+            ("2:2-3:$".to_string(), "xy".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn triple_quoted_string() {
+    let input = "  \"\"\"\n    abc\n\n      def\\n\n    \"\"\"\n\"\"\"a\"b\n  c\"\"\"";
+    assert_eq!(
+        string_literals(input),
+        [
+            ("0:2-4:7".to_string(), "abc\n\n  def\n".to_string()),
+            ("5:0-6:$".to_string(), "a\"b\nc".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn unterminated_multi_line_string() {
+    let input = "\"abc\ndef\n";
+    let mut lexer = Lexer::from_str("test", input);
+    match lexer.next() {
+        Err(Error::UnterminatedStringLiteral(start)) => assert_eq!(format!("{start:?}"), "0:0"),
+        _ => panic!("string literal should be unterminated"),
+    }
+}
+
+#[test]
+fn unicode_identifier() {
+    let input = "音量 = ｆ(周波数2, café)　+ 1;";
+    let mut lexer = Lexer::from_str("test", input);
+    let mut tokens = Vec::new();
+    while let Some((range, token)) = lexer.next().ok().flatten() {
+        tokens.push(format!("{range:?} {token}"));
+    }
+    assert_eq!(
+        tokens,
+        [
+            "0:0-0:2 音量",
+            "0:3-0:4 =",
+            "0:5-0:6 ｆ",
+            "0:6-0:7 (",
+            "0:7-0:11 周波数2",
+            "0:11-0:12 ,",
+            "0:13-0:17 café",
+            "0:17-0:18 )",
+            "0:19-0:20 +",
+            "0:21-0:22 1",
+            "0:22-0:$ ;",
+        ]
+    );
+}
+
+#[test]
+fn unexpected_character_column() {
+    let input = "あい @";
+    let mut lexer = Lexer::from_str("test", input);
+    match lexer.next() {
+        Err(Error::UnexpectedCharacter(start)) => assert_eq!(start.to_string(), "test:1:4"),
+        _ => panic!("`@` should be unexpected"),
+    }
+}
+
+#[test]
+fn source_name() {
+    let path = std::env::temp_dir().join(format!("cryss-{}.cry", std::process::id()));
+    std::fs::write(&path, "a\n  b c").unwrap();
+    let mut lexer = Lexer::from_path(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let mut ranges = Vec::new();
+    while let Some((range, _)) = lexer.next().ok().flatten() {
+        ranges.push(range.to_string());
+    }
+    let name = path.display();
+    assert_eq!(
+        ranges,
+        [
+            format!("{name}:1:1-1:1"),
+            format!("{name}:2:3-2:3"),
+            format!("{name}:2:5-2:$"),
+        ]
+    );
+}
+
+#[test]
+fn double_asterisk() {
+    let mut lexer = Lexer::from_str("test", "a ** b **= c * d");
+    let mut kinds = Vec::new();
+    while let Some((_, token)) = lexer.next().ok().flatten() {
+        kinds.push(token.kind());
+    }
+    assert_eq!(
+        kinds,
+        [
+            "Identifier",
+            "DoubleAsterisk",
+            "Identifier",
+            "DoubleAsteriskEqual",
+            "Identifier",
+            "Asterisk",
+            "Identifier"
+        ]
+    );
+}
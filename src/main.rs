@@ -1,28 +1,153 @@
+mod check;
+mod dump;
 mod error;
+mod eval;
 mod expr;
+mod fold;
 mod lexer;
 mod parser;
 mod pos;
 mod sentence;
+mod sound;
+mod tempo;
 mod token;
 mod types;
+mod value;
+
+use std::io::IsTerminal;
+
+enum Mode {
+    Run,
+    // 構文解析の結果だけを出力する
+    Ast,
+    // 翻訳したバイトコードだけを出力する
+    Disasm,
+    // 字句解析の結果だけを出力する
+    Tokens,
+    TokensJson,
+}
+
+enum Input {
+    Stdin,
+    Path(String),
+    // `-e` で与えられたコード
+    Code(String),
+}
 
 fn main() {
-    let mut lexer = lexer::Lexer::new(Box::new(std::io::BufReader::new(std::io::stdin())), true);
-    loop {
-        match parser::parse_sentence(&mut lexer) {
-            Ok(Some((range, sentence))) => {
-                let sentence: sentence::Sentence = sentence.into();
-                println!("{}({0:?})\n{:#?}", range, sentence);
+    // 再帰の深い関数呼び出しに耐えられるよう，大きなスタックを持つスレッドで実行する
+    std::thread::Builder::new()
+        .stack_size(eval::STACK_SIZE)
+        .spawn(run)
+        .expect("failed to spawn the interpreter thread")
+        .join()
+        .expect("the interpreter thread panicked");
+}
+
+fn run() {
+    let mut mode = Mode::Run;
+    let mut input = Input::Stdin;
+    let mut backend = eval::Backend::Vm;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ast" => mode = Mode::Ast,
+            "--disasm" => mode = Mode::Disasm,
+            "--tokens" => mode = Mode::Tokens,
+            "--tokens-json" => mode = Mode::TokensJson,
+            // バイトコードを使わず構文木をたどって実行する
+            "--tree" => backend = eval::Backend::Tree,
+            "-e" if matches!(input, Input::Stdin) => match args.next() {
+                Some(code) => input = Input::Code(code),
+                None => usage(),
+            },
+            _ if !arg.starts_with('-') && matches!(input, Input::Stdin) => input = Input::Path(arg),
+            _ => usage(),
+        }
+    }
+    let mut interpreter = eval::Interpreter::new();
+    interpreter.set_backend(backend);
+    let mut path = None;
+    let mut prompt = false;
+    let mut lexer = match input {
+        Input::Code(code) => lexer::Lexer::from_str("<command line>", &code),
+        Input::Path(arg) => match lexer::Lexer::from_path(&arg) {
+            Ok(lexer) => {
+                path = Some(arg);
+                lexer
             }
-            Ok(None) => {
-                println!("end");
-                break;
+            Err(err) => {
+                eprintln!("error: cannot open {arg}: {err}");
+                std::process::exit(1);
             }
+        },
+        Input::Stdin => {
+            // JSON を出力するときはプロンプトを混ぜない
+            prompt = std::io::stdin().is_terminal() && !matches!(mode, Mode::TokensJson);
+            let reader = Box::new(std::io::BufReader::new(std::io::stdin()));
+            lexer::Lexer::new(reader, pos::Source::new("<stdin>"), prompt)
+        }
+    };
+    let result = match (mode, path) {
+        (Mode::Run, Some(path)) => interpreter.run_file(lexer, path.as_ref()).map(|_| ()),
+        (Mode::Run, None) if prompt => repl(&mut interpreter, &mut lexer),
+        (Mode::Run, None) => interpreter.run(&mut lexer, &eval::Scope::new(None)),
+        (Mode::Ast, _) => parse(&mut lexer),
+        (Mode::Disasm, _) => disasm(&mut lexer),
+        (Mode::Tokens, _) => dump::tokens(&mut lexer, &mut std::io::stdout()),
+        (Mode::TokensJson, _) => dump::tokens_json(&mut lexer, &mut std::io::stdout()),
+    };
+    if let Err(error) = result {
+        error.eprint();
+        std::process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: cryss [--tree] [--ast | --disasm | --tokens | --tokens-json] [FILE | -e CODE]"
+    );
+    std::process::exit(2);
+}
+
+// 文を 1 つずつ検査して実行する
+//
+// 式の値を出力し，エラーが起きても続ける
+fn repl(interpreter: &mut eval::Interpreter, lexer: &mut lexer::Lexer) -> Result<(), error::Error> {
+    let scope = eval::Scope::new(None);
+    loop {
+        let result = parser::parse_sentence(lexer).and_then(|sentence| match sentence {
+            Some((pos, sentence)) => interpreter.exec((pos, sentence.into()), &scope).map(Some),
+            None => Ok(None),
+        });
+        match result {
+            Ok(Some(value::Value::Void)) => {}
+            Ok(Some(value)) => println!("{value}"),
+            Ok(None) => return Ok(()),
             Err(error) => {
-                error.eprint(lexer.log());
-                return;
+                error.eprint();
+                lexer.discard_line();
             }
         }
     }
 }
+
+fn parse(lexer: &mut lexer::Lexer) -> Result<(), error::Error> {
+    while let Some((range, sentence)) = parser::parse_sentence(lexer)? {
+        let sentence: sentence::Sentence = sentence.into();
+        println!("{}({0:?})\n{:#?}", range, sentence);
+    }
+    println!("end");
+    Ok(())
+}
+
+// 検査と畳み込みを済ませた文を翻訳して出力する
+fn disasm(lexer: &mut lexer::Lexer) -> Result<(), error::Error> {
+    while let Some((range, sentence)) = parser::parse_sentence(lexer)? {
+        let sentence = (range, sentence.into());
+        check::sentence(&sentence)?;
+        let sentence = fold::sentence(sentence);
+        println!("{}\n{}", sentence.0, eval::compile::sentence(&sentence));
+    }
+    Ok(())
+}
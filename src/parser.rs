@@ -1,10 +1,62 @@
 use crate::error::Error;
 use crate::expr;
 use crate::lexer::Lexer;
+use crate::pos;
 use crate::sentence;
 use crate::token::Token;
 
+mod test;
+
 pub fn parse_sentence(lexer: &mut Lexer) -> Result<Option<sentence::PPreSentence>, Error> {
+    if let Some((pos_import, _)) = lexer.next_if(Token::is_keyword_import)? {
+        let (pos_path, path) = match lexer.next()? {
+            Some((pos_path, Token::String(path))) => (pos_path, path),
+            Some((pos, _)) => return Err(Error::UnexpectedTokenAfterImport(pos_import, pos)),
+            None => return Err(Error::UnexpectedEOFAfterImport(pos_import)),
+        };
+        // モジュールを束縛する名前（省略するとファイル名から拡張子を除いたもの）
+        let name = match lexer.next_if(Token::is_keyword_as)? {
+            Some((pos_as, _)) => match lexer.next()? {
+                Some((pos_name, Token::Identifier(name))) => Some((pos_name, name)),
+                Some((pos, _)) => return Err(Error::UnexpectedTokenAfterAs(pos_as, pos)),
+                None => return Err(Error::UnexpectedEOFAfterAs(pos_as)),
+            },
+            None => None,
+        };
+        let pos_semicolon = match lexer.next()? {
+            Some((pos_semicolon, Token::Semicolon)) => pos_semicolon,
+            Some((pos, _)) => return Err(Error::UnexpectedToken(pos)),
+            None => {
+                let pos = name.as_ref().map_or(&pos_path, |(pos, _)| pos);
+                return Err(Error::NoSemicolon(&pos_import + pos));
+            }
+        };
+        let pos = pos_import + pos_semicolon;
+        let sentence = sentence::PreSentence::Import(pos_path, path, name);
+        return Ok(Some((pos, sentence)));
+    }
+    if let Some((pos_return, _)) = lexer.next_if(Token::is_keyword_return)? {
+        let expr = parse_expr(lexer)?;
+        let pos_semicolon = match lexer.next()? {
+            Some((pos_semicolon, Token::Semicolon)) => pos_semicolon,
+            Some((pos, _)) => return Err(Error::UnexpectedToken(pos)),
+            None => {
+                let pos = match &expr {
+                    Some((pos, _)) => &pos_return + pos,
+                    None => pos_return,
+                };
+                return Err(Error::NoSemicolon(pos));
+            }
+        };
+        let pos = pos_return + pos_semicolon;
+        return Ok(Some((pos, sentence::PreSentence::Return(expr))));
+    }
+    // 文の始めの `{` はブロック（`{ name: ...` と続けばレコードの式）
+    if matches!(lexer.peek(0)?, Some(Token::OpeningBrace)) && !starts_record(lexer)? {
+        let (pos_open, _) = lexer.next()?.unwrap();
+        let (pos, sentences) = parse_block(lexer, pos_open)?;
+        return Ok(Some((pos, sentence::PreSentence::Block(sentences))));
+    }
     match (parse_expr(lexer)?, lexer.next()?) {
         (expr, Some((pos_semicolon, Token::Semicolon))) => {
             let pos = match &expr {
@@ -13,11 +65,72 @@ pub fn parse_sentence(lexer: &mut Lexer) -> Result<Option<sentence::PPreSentence
             };
             Ok(Some((pos, sentence::PreSentence::Expr(expr))))
         }
+        (Some(declaration), Some((pos_open, Token::OpeningBrace))) => {
+            parse_function(lexer, declaration, pos_open).map(Some)
+        }
         (None, None) => Ok(None),
-        _ => panic!(),
+        (Some((pos, _)), None) => Err(Error::NoSemicolon(pos)),
+        (_, Some((pos, _))) => Err(Error::UnexpectedToken(pos)),
+    }
+}
+
+// 次の `{` の後に名前と `:` が続くか
+fn starts_record(lexer: &mut Lexer) -> Result<bool, Error> {
+    Ok(matches!(lexer.peek(1)?, Some(Token::Identifier(_)))
+        && matches!(lexer.peek(2)?, Some(Token::Colon)))
+}
+
+// `{` の後から対応する `}` までを読む
+fn parse_block(
+    lexer: &mut Lexer,
+    pos_open: pos::Range,
+) -> Result<(pos::Range, Vec<sentence::PPreSentence>), Error> {
+    let mut sentences = Vec::new();
+    loop {
+        if let Some((pos_close, _)) = lexer.next_if(Token::is_closing_brace)? {
+            return Ok((pos_open + pos_close, sentences));
+        }
+        match parse_sentence(lexer)? {
+            Some(sentence) => sentences.push(sentence),
+            None => return Err(Error::NoClosingBrace(pos_open)),
+        }
     }
 }
 
+// `name(param, ...)` の後に `{` が続けば関数の定義
+fn parse_function(
+    lexer: &mut Lexer,
+    (pos_declaration, declaration): expr::PPreExpr,
+    pos_open: pos::Range,
+) -> Result<sentence::PPreSentence, Error> {
+    let (name, args, named_args) = match declaration {
+        expr::PreExpr::Call(name, args, named_args) => match *name {
+            (pos, expr::PreExpr::Identifier(name)) => ((pos, name), args, named_args),
+            (pos, _) => return Err(Error::InvalidFunctionName(pos)),
+        },
+        _ => return Err(Error::UnexpectedToken(pos_open)),
+    };
+    // 既定値のない引数は位置による引数の形で，既定値のある引数は名前付きの引数の形で書く
+    let mut params: Vec<(expr::PName, Option<expr::PPreExpr>)> = Vec::new();
+    let args = args.into_iter().map(|arg| match arg {
+        (pos, expr::PreExpr::Identifier(param)) => Ok(((pos, param), None)),
+        (pos, _) => Err(Error::InvalidParameter(pos)),
+    });
+    let named_args = named_args
+        .into_iter()
+        .map(|(param, default)| Ok((param, Some(default))));
+    for param in args.chain(named_args) {
+        let param = param?;
+        if params.iter().any(|((_, name), _)| *name == param.0 .1) {
+            return Err(Error::DuplicateParameter(param.0 .0));
+        }
+        params.push(param);
+    }
+    let (pos_body, body) = parse_block(lexer, pos_open)?;
+    let pos = pos_declaration + pos_body;
+    Ok((pos, sentence::PreSentence::Function(name, params, body)))
+}
+
 fn parse_expr(lexer: &mut Lexer) -> Result<Option<expr::PPreExpr>, Error> {
     parse_bin_op(lexer, Precedence::first().unwrap())
 }
@@ -37,12 +150,20 @@ fn parse_factor(lexer: &mut Lexer) -> Result<Option<expr::PPreExpr>, Error> {
         (pos, expr::PreExpr::Float(s))
     } else if let Some((pos, Token::String(s))) = lexer.next_if(Token::is_string)? {
         (pos, expr::PreExpr::String(s))
+    } else if let Some((pos, value)) = lexer.next_if_map(|token| match token {
+        Token::KeywordTrue => Some(true),
+        Token::KeywordFalse => Some(false),
+        _ => None,
+    })? {
+        (pos, expr::PreExpr::Boolean(value))
     } else if let Some(op) = lexer.next_if_map(|token| match token {
         Token::Plus => Some(expr::UnOp::Plus),
         Token::Hyphen => Some(expr::UnOp::Minus),
         Token::Slash => Some(expr::UnOp::Recip),
         Token::Exclamation => Some(expr::UnOp::LogicalNot),
         Token::Tilde => Some(expr::UnOp::BitNot),
+        Token::DoublePlus => Some(expr::UnOp::PreIncrement),
+        Token::DoubleHyphen => Some(expr::UnOp::PreDecrement),
         _ => None,
     })? {
         match parse_factor(lexer)? {
@@ -67,19 +188,60 @@ fn parse_factor(lexer: &mut Lexer) -> Result<Option<expr::PPreExpr>, Error> {
         };
         let pos = pos_open + pos_close;
         (pos, expr)
+    } else if let Some((pos_open, _)) = lexer.next_if(Token::is_opening_brace)? {
+        // 式の中の `{` はレコード
+        let fields = parse_fields(lexer)?;
+        let pos_close = match lexer.next()? {
+            Some((pos_close, Token::ClosingBrace)) => pos_close,
+            Some((pos, _)) => return Err(Error::UnexpectedTokenInBrace(pos_open, pos)),
+            None => return Err(Error::NoClosingBrace(pos_open)),
+        };
+        (pos_open + pos_close, expr::PreExpr::Record(fields))
+    } else if let Some((pos_open, _)) = lexer.next_if(Token::is_opening_bracket)? {
+        let elems = parse_list(lexer, parse_expr)?;
+        let pos_close = parse_closing_bracket(lexer, &pos_open)?;
+        (pos_open + pos_close, expr::PreExpr::List(elems))
     } else {
         return Ok(None);
     };
     loop {
         if let Some((pos_open, _)) = lexer.next_if(Token::is_opening_parenthesis)? {
-            let args = parse_list(lexer)?;
+            let (args, named_args) = parse_args(lexer)?;
             let pos_close = match lexer.next()? {
                 Some((pos_close, Token::ClosingParenthesis)) => pos_close,
                 Some((pos, _)) => return Err(Error::UnexpectedTokenInParenthesis(pos_open, pos)),
                 None => return Err(Error::NoClosingParenthesis(pos_open)),
             };
             let pos = &ret.0 + pos_close;
-            let expr = expr::PreExpr::Call(ret.into(), args);
+            let expr = expr::PreExpr::Call(ret.into(), args, named_args);
+            ret = (pos, expr);
+        } else if let Some((pos_open, _)) = lexer.next_if(Token::is_opening_bracket)? {
+            let index = parse_expr(lexer)?;
+            let pos_close = parse_closing_bracket(lexer, &pos_open)?;
+            let index = match index {
+                Some(index) => index,
+                None => return Err(Error::EmptyIndex(pos_open, pos_close)),
+            };
+            let pos = &ret.0 + pos_close;
+            let expr = expr::PreExpr::Index(ret.into(), index.into());
+            ret = (pos, expr);
+        } else if let Some(op) = lexer.next_if_map(|token| match token {
+            Token::DoublePlus => Some(expr::UnOp::PostIncrement),
+            Token::DoubleHyphen => Some(expr::UnOp::PostDecrement),
+            _ => None,
+        })? {
+            let pos = &ret.0 + &op.0;
+            let expr = expr::PreExpr::UnOp(op, ret.into());
+            ret = (pos, expr);
+        } else if let Some((pos_dot, _)) = lexer.next_if(Token::is_dot)? {
+            // `a.f(x)` は `Member` の呼び出しとして読み，評価するときに区別する
+            let name = match lexer.next()? {
+                Some((pos, Token::Identifier(name))) => (pos, name),
+                Some((pos, _)) => return Err(Error::UnexpectedTokenAfterDot(pos_dot, pos)),
+                None => return Err(Error::UnexpectedEOFAfterDot(pos_dot)),
+            };
+            let pos = &ret.0 + &name.0;
+            let expr = expr::PreExpr::Member(ret.into(), name);
             ret = (pos, expr);
         } else {
             return Ok(Some(ret));
@@ -87,10 +249,20 @@ fn parse_factor(lexer: &mut Lexer) -> Result<Option<expr::PPreExpr>, Error> {
     }
 }
 
+fn parse_closing_bracket(lexer: &mut Lexer, pos_open: &pos::Range) -> Result<pos::Range, Error> {
+    match lexer.next()? {
+        Some((pos_close, Token::ClosingBracket)) => Ok(pos_close),
+        Some((pos, _)) => Err(Error::UnexpectedTokenInBracket(pos_open.clone(), pos)),
+        None => Err(Error::NoClosingBracket(pos_open.clone())),
+    }
+}
+
 use enum_iterator::Sequence;
 #[derive(Clone, Copy, Sequence, PartialEq, Eq)]
 enum Precedence {
     Assign,
+    // 三項演算子 `cond ? a : b`
+    Conditional,
     TimeShift,
     LogicalOr,
     LogicalAnd,
@@ -101,12 +273,14 @@ enum Precedence {
     BitShift,
     AddSub,
     MulDivRem,
+    Pow,
     Max,
 }
 
 impl expr::BinOp {
     fn precedence(&self) -> Precedence {
         match self {
+            Self::Pow => Precedence::Pow,
             Self::Mul | Self::Div | Self::Rem => Precedence::MulDivRem,
             Self::Add | Self::Sub => Precedence::AddSub,
             Self::LeftShift | Self::RightShift => Precedence::BitShift,
@@ -127,6 +301,7 @@ impl expr::BinOp {
             | Self::MulAssign
             | Self::DivAssign
             | Self::RemAssign
+            | Self::PowAssign
             | Self::LeftShiftAssign
             | Self::RightShiftAssign
             | Self::BitAndAssign
@@ -146,7 +321,7 @@ enum Assoc {
 impl Precedence {
     fn assoc(&self) -> Assoc {
         match self {
-            Precedence::Assign => Assoc::RightToLeft,
+            Precedence::Assign | Precedence::Conditional | Precedence::Pow => Assoc::RightToLeft,
             _ => Assoc::LeftToRight,
         }
     }
@@ -161,6 +336,9 @@ fn parse_bin_op(lexer: &mut Lexer, prec: Precedence) -> Result<Option<expr::PPre
         Some(expr) => expr,
         None => return Ok(None),
     };
+    if prec == Precedence::Conditional {
+        return parse_conditional(lexer, ret);
+    }
     loop {
         if let Some(op) = lexer.next_if_map(|token| {
             let op = match token {
@@ -174,6 +352,8 @@ fn parse_bin_op(lexer: &mut Lexer, prec: Precedence) -> Result<Option<expr::PPre
                 Token::SlashEqual => expr::BinOp::DivAssign,
                 Token::Percent => expr::BinOp::Rem,
                 Token::PercentEqual => expr::BinOp::RemAssign,
+                Token::DoubleAsterisk => expr::BinOp::Pow,
+                Token::DoubleAsteriskEqual => expr::BinOp::PowAssign,
                 Token::Equal => expr::BinOp::Assign,
                 Token::DoubleEqual => expr::BinOp::Equal,
                 Token::ExclamationEqual => expr::BinOp::NotEqual,
@@ -199,7 +379,7 @@ fn parse_bin_op(lexer: &mut Lexer, prec: Precedence) -> Result<Option<expr::PPre
                 Token::CircumflexEqual => expr::BinOp::BitXorAssign,
                 _ => return None,
             };
-            (op.precedence() == prec).then(|| op)
+            (op.precedence() == prec).then_some(op)
         })? {
             let assoc = prec.assoc();
             let right = match parse_bin_op(
@@ -218,7 +398,19 @@ fn parse_bin_op(lexer: &mut Lexer, prec: Precedence) -> Result<Option<expr::PPre
                 }
             };
             let pos = &ret.0 + &right.0;
-            let expr = expr::PreExpr::BinOp(op, ret.into(), right.into());
+            let expr = match (prec, ret.1) {
+                // `a < b < c` は `a < b && b < c`（b は 1 度だけ評価する）
+                (Precedence::Comparison, expr::PreExpr::BinOp(first_op, first, second))
+                    if first_op.1.precedence() == Precedence::Comparison =>
+                {
+                    expr::PreExpr::Comparison(first, vec![(first_op, *second), (op, right)])
+                }
+                (Precedence::Comparison, expr::PreExpr::Comparison(first, mut rest)) => {
+                    rest.push((op, right));
+                    expr::PreExpr::Comparison(first, rest)
+                }
+                (_, left) => expr::PreExpr::BinOp(op, (ret.0, left).into(), right.into()),
+            };
             ret = (pos, expr);
             if matches!(assoc, Assoc::LeftToRight) {
                 continue;
@@ -228,10 +420,43 @@ fn parse_bin_op(lexer: &mut Lexer, prec: Precedence) -> Result<Option<expr::PPre
     }
 }
 
-fn parse_list(lexer: &mut Lexer) -> Result<Vec<expr::PPreExpr>, Error> {
+// 条件 `cond` を読んだ後，`? a : b` が続けば読む
+fn parse_conditional(
+    lexer: &mut Lexer,
+    cond: expr::PPreExpr,
+) -> Result<Option<expr::PPreExpr>, Error> {
+    let pos_question = match lexer.next_if(Token::is_question)? {
+        Some((pos_question, _)) => pos_question,
+        None => return Ok(Some(cond)),
+    };
+    let unexpected = |token: Option<_>| match token {
+        Some((pos, _)) => Error::UnexpectedTokenInConditional(pos_question.clone(), pos),
+        None => Error::UnexpectedEOFInConditional(pos_question.clone()),
+    };
+    let then = match parse_expr(lexer)? {
+        Some(then) => then,
+        None => return Err(unexpected(lexer.next()?)),
+    };
+    if lexer.next_if(Token::is_colon)?.is_none() {
+        return Err(unexpected(lexer.next()?));
+    }
+    // 右結合
+    let otherwise = match parse_bin_op(lexer, Precedence::Conditional)? {
+        Some(otherwise) => otherwise,
+        None => return Err(unexpected(lexer.next()?)),
+    };
+    let pos = &cond.0 + &otherwise.0;
+    let expr = expr::PreExpr::Conditional(cond.into(), then.into(), otherwise.into());
+    Ok(Some((pos, expr)))
+}
+
+fn parse_list<T>(
+    lexer: &mut Lexer,
+    parse_elem: impl Fn(&mut Lexer) -> Result<Option<(pos::Range, T)>, Error>,
+) -> Result<Vec<(pos::Range, T)>, Error> {
     let mut ret = Vec::new();
     loop {
-        let elem = parse_expr(lexer)?;
+        let elem = parse_elem(lexer)?;
         if let Some((pos_comma, _)) = lexer.next_if(Token::is_comma)? {
             match elem {
                 Some(elem) => ret.push(elem),
@@ -245,3 +470,64 @@ fn parse_list(lexer: &mut Lexer) -> Result<Vec<expr::PPreExpr>, Error> {
         }
     }
 }
+
+enum Arg {
+    Positional(expr::PreExpr),
+    Named(expr::PName, expr::PPreExpr),
+}
+
+// 関数呼び出しの引数
+//
+// `name: value` の形の名前付きの引数は，位置による引数より後に書く
+fn parse_args(lexer: &mut Lexer) -> Result<(Vec<expr::PPreExpr>, Vec<expr::PPreNamedArg>), Error> {
+    let mut args = Vec::new();
+    let mut named_args: Vec<expr::PPreNamedArg> = Vec::new();
+    for (pos, arg) in parse_list(lexer, parse_arg)? {
+        match arg {
+            Arg::Positional(arg) => {
+                if !named_args.is_empty() {
+                    return Err(Error::PositionalArgumentAfterNamed(pos));
+                }
+                args.push((pos, arg));
+            }
+            Arg::Named((pos, name), value) => {
+                if named_args.iter().any(|((_, prev), _)| *prev == name) {
+                    return Err(Error::DuplicateArgument(pos));
+                }
+                named_args.push(((pos, name), value));
+            }
+        }
+    }
+    Ok((args, named_args))
+}
+
+// レコードのフィールド `name: value, ...`
+fn parse_fields(lexer: &mut Lexer) -> Result<Vec<expr::PPreNamedArg>, Error> {
+    let mut fields: Vec<expr::PPreNamedArg> = Vec::new();
+    for (pos, field) in parse_list(lexer, parse_arg)? {
+        match field {
+            Arg::Positional(_) => return Err(Error::NoFieldName(pos)),
+            Arg::Named((pos, name), value) => {
+                if fields.iter().any(|((_, prev), _)| *prev == name) {
+                    return Err(Error::DuplicateField(pos));
+                }
+                fields.push(((pos, name), value));
+            }
+        }
+    }
+    Ok(fields)
+}
+
+fn parse_arg(lexer: &mut Lexer) -> Result<Option<(pos::Range, Arg)>, Error> {
+    match (parse_expr(lexer)?, lexer.next_if(Token::is_colon)?) {
+        (Some((pos, expr::PreExpr::Identifier(name))), Some((pos_colon, _))) => {
+            match parse_expr(lexer)? {
+                Some(value) => Ok(Some((&pos + &value.0, Arg::Named((pos, name), value)))),
+                None => Err(Error::NoArgumentAfterColon(pos_colon)),
+            }
+        }
+        (Some((pos, _)), Some(_)) => Err(Error::InvalidArgumentName(pos)),
+        (None, Some((pos_colon, _))) => Err(Error::UnexpectedToken(pos_colon)),
+        (arg, None) => Ok(arg.map(|(pos, arg)| (pos, Arg::Positional(arg)))),
+    }
+}
@@ -0,0 +1,33 @@
+#![cfg(test)]
+
+use crate::expr::Expr;
+use crate::lexer::Lexer;
+use crate::sentence::Sentence;
+
+fn parse(input: &str) -> Sentence {
+    let mut lexer = Lexer::from_str("test", input);
+    let (_, sentence) = super::parse_sentence(&mut lexer).ok().flatten().unwrap();
+    sentence.into()
+}
+
+// 文の始めの `{` の後に `名前:` が続けばレコード，そうでなければブロック
+#[test]
+fn record_or_block() {
+    match parse("{ freq: 440 } == { freq: 440 };") {
+        Sentence::Expr(Some((_, Expr::BinOp(_, left, _)))) => {
+            assert!(matches!(left.1, Expr::Record(_)));
+        }
+        sentence => panic!("{sentence:?}"),
+    }
+    // 行をまたいでも見分ける
+    match parse("{\n  freq:\n  440 }.freq;") {
+        Sentence::Expr(Some(_)) => (),
+        sentence => panic!("{sentence:?}"),
+    }
+    for input in ["{ freq = 440; }", "{ freq; }", "{}"] {
+        match parse(input) {
+            Sentence::Block(_) => (),
+            sentence => panic!("{input}: {sentence:?}"),
+        }
+    }
+}
@@ -1,5 +1,21 @@
+use std::cell::{Ref, RefCell};
+use std::rc::Rc;
+
+mod test;
+
+// 入力元（ファイルや標準入力）
+//
+// 読んだ行を貯めておき，エラーの表示に使う
+//
+// 位置 (`Range`, `Start`) は入力元への参照を持ち，これを入力元の識別に使う
+pub struct Source {
+    name: String,
+    log: RefCell<Vec<String>>,
+}
+
 #[derive(Clone)]
 pub struct Start {
+    source: Rc<Source>,
     line: usize,
     column: usize,
 }
@@ -16,9 +32,31 @@ pub struct Range {
     end: End,
 }
 
+impl Source {
+    pub fn new(name: impl Into<String>) -> Rc<Source> {
+        Rc::new(Source {
+            name: name.into(),
+            log: RefCell::new(Vec::new()),
+        })
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn log(&self) -> Ref<'_, [String]> {
+        Ref::map(self.log.borrow(), Vec::as_slice)
+    }
+    pub fn push_line(&self, line: String) {
+        self.log.borrow_mut().push(line);
+    }
+}
+
 impl Start {
-    pub fn new(line: usize, column: usize) -> Self {
-        Self { line, column }
+    pub fn new(source: Rc<Source>, line: usize, column: usize) -> Self {
+        Self {
+            source,
+            line,
+            column,
+        }
     }
 }
 impl End {
@@ -31,21 +69,37 @@ impl Range {
         Self { start, end }
     }
 
-    pub fn new_single_line(line: usize, start: usize, end: Option<usize>) -> Range {
+    pub fn new_single_line(
+        source: Rc<Source>,
+        line: usize,
+        start: usize,
+        end: Option<usize>,
+    ) -> Range {
         Range {
             start: Start {
+                source,
                 line,
                 column: start,
             },
             end: End { line, column: end },
         }
     }
+
+    pub fn source(&self) -> &Rc<Source> {
+        &self.start.source
+    }
 }
 
 use std::fmt::{self, Debug, Display, Formatter};
 impl Display for Start {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "{}:{}", self.line + 1, self.column + 1)
+        write!(
+            f,
+            "{}:{}:{}",
+            self.source.name,
+            self.line + 1,
+            self.column + 1
+        )
     }
 }
 impl Debug for Start {
@@ -82,46 +136,111 @@ impl Debug for Range {
     }
 }
 
+impl Range {
+    // 行と列は 0 から数える（列は文字単位）．終わりの列はその位置を含まず，行末までなら null
+    pub fn to_json(&self) -> String {
+        let end_column = match self.end.column {
+            Some(column) => column.to_string(),
+            None => "null".to_string(),
+        };
+        format!(
+            r#"{{"start":{{"line":{},"column":{}}},"end":{{"line":{},"column":{}}}}}"#,
+            self.start.line, self.start.column, self.end.line, end_column
+        )
+    }
+}
+
+impl Range {
+    // 範囲の元の文字列（行をまたぐときは改行も含む）
+    pub fn text(&self) -> String {
+        let log = self.start.source.log();
+        let mut out = String::new();
+        for line_num in self.start.line..=self.end.line {
+            let line = &log[line_num];
+            let start = if line_num == self.start.line {
+                byte_index(line, self.start.column)
+            } else {
+                0
+            };
+            let end = match self.end.column {
+                Some(column) if line_num == self.end.line => byte_index(line, column),
+                _ => line.len(),
+            };
+            out += &line[start..end];
+        }
+        out
+    }
+}
+
+// 文字単位の列をバイト単位の位置に直す
+fn byte_index(line: &str, column: usize) -> usize {
+    line.char_indices()
+        .nth(column)
+        .map_or(line.len(), |(index, _)| index)
+}
+
+// 行の終わりに改行がなければ足す（-e で与えたコードなどの最後の行）
+fn end_line(out: &mut String) {
+    if !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
 impl Start {
-    pub fn eprint(&self, log: &[String]) {
+    pub fn eprint(&self) {
+        eprint!("{}", self.excerpt());
+    }
+    // 位置に印を付けた行
+    fn excerpt(&self) -> String {
+        let log = self.source.log();
         let line = &log[self.line];
-        eprint!("{} !-> {}", &line[..self.column], &line[self.column..]);
+        let start = byte_index(line, self.column);
+        let mut out = format!("{} !-> {}", &line[..start], &line[start..]);
+        end_line(&mut out);
+        out
     }
 }
 
 impl Range {
-    pub fn eprint(&self, log: &[String]) {
+    pub fn eprint(&self) {
+        eprint!("{}", self.excerpt());
+    }
+    // 範囲の始めと終わりに印を付けた行
+    fn excerpt(&self) -> String {
+        let log = self.start.source.log();
+        let mut out = String::new();
         if self.start.line == self.end.line {
             let line = &log[self.start.line];
-            let start = self.start.column;
+            let start = byte_index(line, self.start.column);
             match self.end.column {
                 Some(end) => {
-                    eprint!(
+                    let end = byte_index(line, end);
+                    out += &format!(
                         "{} !-> {} <-! {}",
                         &line[..start],
                         &line[start..end],
                         &line[end..]
-                    )
-                }
-                None => {
-                    eprintln!("{} !-> {} <-!", &line[..start], &line[start..])
+                    );
                 }
+                None => out += &format!("{} !-> {} <-!", &line[..start], &line[start..]),
             }
         } else {
             let sline = &log[self.start.line];
-            let start = self.start.column;
-            eprint!("{} !-> {}", &sline[..start], &sline[start..]);
-            eprintln!("...");
+            let start = byte_index(sline, self.start.column);
+            out += &format!("{} !-> {}", &sline[..start], &sline[start..]);
+            end_line(&mut out);
+            out += "...\n";
             let eline = &log[self.end.line];
             match self.end.column {
                 Some(end) => {
-                    eprint!("{} <-! {}", &eline[..end], &eline[end..])
-                }
-                None => {
-                    eprintln!("{} <-!", &eline)
+                    let end = byte_index(eline, end);
+                    out += &format!("{} <-! {}", &eline[..end], &eline[end..]);
                 }
+                None => out += &format!("{} <-!", &eline),
             }
         }
+        end_line(&mut out);
+        out
     }
 }
 
@@ -0,0 +1,19 @@
+#![cfg(test)]
+
+use super::{End, Range, Source, Start};
+
+#[test]
+fn excerpt() {
+    let source = Source::new("test");
+    source.push_line("a = 1;\n".to_owned());
+    // -e で与えたコードの最後の行には改行がない
+    source.push_line("print(1 / 0);".to_owned());
+    let range = Range::new_single_line(source.clone(), 1, 6, Some(11));
+    assert_eq!(range.excerpt(), "print( !-> 1 / 0 <-! );\n");
+    let range = Range::new_single_line(source.clone(), 0, 4, Some(5));
+    assert_eq!(range.excerpt(), "a =  !-> 1 <-! ;\n");
+    let start = Start::new(source.clone(), 1, 12);
+    assert_eq!(start.excerpt(), "print(1 / 0) !-> ;\n");
+    let range = Range::new(Start::new(source.clone(), 0, 4), End::new(1, Some(5)));
+    assert_eq!(range.excerpt(), "a =  !-> 1;\n...\nprint <-! (1 / 0);\n");
+}
@@ -1,18 +1,70 @@
+use std::rc::Rc;
+
 use crate::expr;
 
 #[derive(Debug)]
 pub enum Sentence {
     Expr(Option<expr::PExpr>),
+    // `import "path";` の path とその位置，`import "path" as name;` の name
+    Import(pos::Range, String, Option<expr::PName>),
+    Block(Vec<PSentence>),
+    Return(Option<expr::PExpr>),
+    Function(Rc<Function>),
+}
+
+// `name(param, ..., param: default, ...) { body }` による関数の定義
+#[derive(Debug)]
+pub struct Function {
+    pub name: expr::PName,
+    pub params: Vec<Parameter>,
+    pub body: Vec<PSentence>,
+}
+
+// 既定値は呼び出しのたびに，それより前の引数が見える有効範囲で評価する
+#[derive(Debug)]
+pub struct Parameter {
+    pub name: expr::PName,
+    pub default: Option<expr::PExpr>,
 }
 
 pub enum PreSentence {
     Expr(Option<expr::PPreExpr>),
+    Import(pos::Range, String, Option<expr::PName>),
+    Block(Vec<PPreSentence>),
+    Return(Option<expr::PPreExpr>),
+    Function(
+        expr::PName,
+        Vec<(expr::PName, Option<expr::PPreExpr>)>,
+        Vec<PPreSentence>,
+    ),
 }
 
 impl From<PreSentence> for Sentence {
     fn from(pre_sentence: PreSentence) -> Sentence {
+        let convert = |sentences: Vec<PPreSentence>| {
+            sentences
+                .into_iter()
+                .map(|(pos, sentence)| (pos, sentence.into()))
+                .collect()
+        };
         match pre_sentence {
             PreSentence::Expr(expr) => Sentence::Expr(expr.map(|(pos, expr)| (pos, expr.into()))),
+            PreSentence::Import(pos, path, name) => Sentence::Import(pos, path, name),
+            PreSentence::Block(sentences) => Sentence::Block(convert(sentences)),
+            PreSentence::Return(expr) => {
+                Sentence::Return(expr.map(|(pos, expr)| (pos, expr.into())))
+            }
+            PreSentence::Function(name, params, body) => Sentence::Function(Rc::new(Function {
+                name,
+                params: params
+                    .into_iter()
+                    .map(|(name, default)| Parameter {
+                        name,
+                        default: default.map(|(pos, expr)| (pos, expr.into())),
+                    })
+                    .collect(),
+                body: convert(body),
+            })),
         }
     }
 }
@@ -0,0 +1,613 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+mod parallel;
+mod test;
+
+// 既定のサンプリング周波数（1 秒あたりのサンプル数）
+pub const SAMPLE_RATE: u32 = 44100;
+
+// 時刻と長さの単位（1 秒を TICKS 等分する）
+//
+// よく使うサンプリング周波数（8000 Hz から 192000 Hz まで）はどれもこれを割り切るので，
+// 遅れを足し合わせてもサンプルの境目からずれない
+pub const TICKS: i64 = 705_600_000;
+
+// 一度に計算するサンプル数
+const BLOCK: usize = 1024;
+
+// 計算していない節点の出力
+static SILENCE: [f64; BLOCK] = [0.; BLOCK];
+
+// 音（チャンネルごとの信号）
+//
+// 長さはいちばん長いチャンネルに揃え，短いチャンネルの足りないサンプルは 0 とする
+#[derive(Clone)]
+pub struct Sound {
+    channels: Vec<Signal>,
+}
+
+// 1 チャンネルの信号
+//
+// サンプルの列は持たず，どう計算するかを表すグラフの節点を持つ．
+// 長さや遅れは TICKS を単位とし，サンプリング周波数は書き出すときに決める．
+// 終わった後は無音として扱う．グラフは書き出すときに別のスレッドからも読む
+#[derive(Clone)]
+pub struct Signal {
+    node: Arc<Node>,
+    len: i64,
+}
+
+// サンプルごとの演算
+#[derive(Clone, Copy)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+enum Node {
+    // 周波数が一定の正弦波
+    Sine(f64),
+    // 周波数の信号に従って位相を進める正弦波
+    Fm(Signal),
+    // 長さの間に直線的に変わる
+    Line(f64, f64),
+    // 読み込んだサンプルの列とそのサンプリング周波数（違う周波数で書き出すときは補間する）
+    Samples(Arc<[f64]>, u32),
+    Neg(Signal),
+    Recip(Signal),
+    // 長い方に揃え，短い方の足りないサンプルは 0 とする
+    Zip(Op, Signal, Signal),
+    // 各サンプルと数の演算（信号が左辺のものと右辺のもの）
+    Left(Op, Signal, f64),
+    Right(Op, f64, Signal),
+    // 遅らせる（負なら早めて，始めを捨てる）．長さを縮めれば終わりも捨てる
+    Shift(Signal, i64),
+}
+
+impl Sound {
+    pub fn mono(signal: Signal) -> Sound {
+        Sound {
+            channels: vec![signal],
+        }
+    }
+    // 空でないこと
+    pub fn from_channels(channels: Vec<Signal>) -> Sound {
+        Sound { channels }
+    }
+    pub fn channels(&self) -> &[Signal] {
+        &self.channels
+    }
+    pub fn map(&self, f: impl Fn(&Signal) -> Signal) -> Sound {
+        Sound::from_channels(self.channels.iter().map(f).collect())
+    }
+    // チャンネルごとに演算する
+    //
+    // チャンネル数が同じでなければ，モノラルの方を各チャンネルに使う（どちらでもなければ None）
+    pub fn zip(&self, other: &Sound, f: impl Fn(&Signal, &Signal) -> Signal) -> Option<Sound> {
+        let (l, r) = (&self.channels, &other.channels);
+        let channels = match (l.len(), r.len()) {
+            (m, n) if m == n => l.iter().zip(r).map(|(l, r)| f(l, r)).collect(),
+            (1, _) => r.iter().map(|r| f(&l[0], r)).collect(),
+            (_, 1) => l.iter().map(|l| f(l, &r[0])).collect(),
+            _ => return None,
+        };
+        Some(Sound::from_channels(channels))
+    }
+    // 遅らせる（負なら早めて，始めを捨てる）
+    pub fn shift(&self, delay: i64) -> Sound {
+        self.map(|signal| signal.shift(delay))
+    }
+    // 長さ len までで切る
+    pub fn take(&self, len: i64) -> Sound {
+        self.map(|signal| signal.take(len))
+    }
+
+    // 長さ（各チャンネルの長さの最大）
+    pub fn len(&self) -> i64 {
+        self.channels.iter().map(|signal| signal.len).max().unwrap()
+    }
+    // 秒数
+    pub fn duration(&self) -> f64 {
+        self.len() as f64 / TICKS as f64
+    }
+
+    // 16 ビットの WAV として書き出す（[-1, 1] の外は切り詰める）
+    //
+    // サンプリング周波数は rate とし，足し合わされた信号は threads 個までのスレッドで分けて計算する．
+    // ヘッダーの大きさの欄に収まらないときは何も書かずに InvalidInput を返す
+    pub fn write_wav(&self, out: &mut impl Write, threads: usize, rate: u32) -> io::Result<()> {
+        let invalid = |message| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        let channels = self.channels.len() as u64;
+        let block_align = channels * 2;
+        let byte_rate = rate as u64 * block_align;
+        let data_size = (samples(self.len(), rate) as u64).saturating_mul(block_align);
+        if block_align > u16::MAX as u64 || byte_rate > u32::MAX as u64 {
+            return invalid("too many channels or too high a sample rate for WAV");
+        }
+        if data_size > (u32::MAX - 36) as u64 {
+            return invalid("sound too long for WAV");
+        }
+        let (channels, block_align) = (channels as u16, block_align as u16);
+        let (byte_rate, data_size) = (byte_rate as u32, data_size as u32);
+        out.write_all(b"RIFF")?;
+        out.write_all(&(36 + data_size).to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        // リニア PCM
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&channels.to_le_bytes())?;
+        out.write_all(&rate.to_le_bytes())?;
+        out.write_all(&byte_rate.to_le_bytes())?;
+        out.write_all(&block_align.to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&data_size.to_le_bytes())?;
+        parallel::render(&self.channels, threads, rate, |chunk| {
+            for &x in chunk {
+                // NaN は 0 になる
+                let sample = (x.clamp(-1., 1.) * i16::MAX as f64).round() as i16;
+                out.write_all(&sample.to_le_bytes())?;
+            }
+            Ok(())
+        })
+    }
+
+    // 16 ビットのリニア PCM の WAV を読む
+    pub fn read_wav(input: &mut impl Read) -> io::Result<Sound> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "unsupported WAV file");
+        let mut data = Vec::new();
+        input.read_to_end(&mut data)?;
+        if data.get(0..4) != Some(b"RIFF") || data.get(8..12) != Some(b"WAVE") {
+            return Err(invalid());
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]);
+        let u32_at = |i: usize| u32::from_le_bytes(data[i..i + 4].try_into().unwrap());
+        // チャンネル数とサンプリング周波数
+        let mut format = None;
+        let mut pos = 12;
+        while pos + 8 <= data.len() {
+            let size = u32_at(pos + 4) as usize;
+            let body = pos + 8..(pos + 8).saturating_add(size).min(data.len());
+            match (&data[pos..pos + 4], format) {
+                (b"fmt ", _) if body.len() >= 16 => {
+                    let (tag, channels, rate, bits) = (
+                        u16_at(body.start),
+                        u16_at(body.start + 2),
+                        u32_at(body.start + 4),
+                        u16_at(body.start + 14),
+                    );
+                    if tag != 1 || bits != 16 || channels == 0 || rate == 0 {
+                        return Err(invalid());
+                    }
+                    format = Some((channels as usize, rate));
+                }
+                (b"data", Some((channels, rate))) => {
+                    let frames = data[body].chunks_exact(2 * channels);
+                    let mut samples = vec![Vec::with_capacity(frames.len()); channels];
+                    for frame in frames {
+                        for (channel, sample) in samples.iter_mut().zip(frame.chunks_exact(2)) {
+                            let sample = i16::from_le_bytes([sample[0], sample[1]]);
+                            channel.push(sample as f64 / i16::MAX as f64);
+                        }
+                    }
+                    let channels = samples
+                        .into_iter()
+                        .map(|samples| Signal::samples(samples, rate))
+                        .collect();
+                    return Ok(Sound::from_channels(channels));
+                }
+                _ => {}
+            }
+            pos = pos.saturating_add(8 + size + size % 2);
+        }
+        Err(invalid())
+    }
+}
+
+impl Signal {
+    fn new(node: Node, len: i64) -> Signal {
+        Signal {
+            node: Arc::new(node),
+            len,
+        }
+    }
+    pub fn sine(frequency: f64, len: i64) -> Signal {
+        Signal::new(Node::Sine(frequency), len)
+    }
+    pub fn fm(frequency: &Signal, len: i64) -> Signal {
+        Signal::new(Node::Fm(frequency.clone()), len)
+    }
+    pub fn line(from: f64, to: f64, len: i64) -> Signal {
+        Signal::new(Node::Line(from, to), len)
+    }
+    pub fn samples(samples: Vec<f64>, rate: u32) -> Signal {
+        let len = samples.len() as i128 * TICKS as i128 / rate as i128;
+        Signal::new(Node::Samples(samples.into(), rate), len as i64)
+    }
+    pub fn neg(&self) -> Signal {
+        Signal::new(Node::Neg(self.clone()), self.len)
+    }
+    pub fn recip(&self) -> Signal {
+        Signal::new(Node::Recip(self.clone()), self.len)
+    }
+    pub fn zip(&self, op: Op, other: &Signal) -> Signal {
+        let len = self.len.max(other.len);
+        Signal::new(Node::Zip(op, self.clone(), other.clone()), len)
+    }
+    pub fn left(&self, op: Op, other: f64) -> Signal {
+        Signal::new(Node::Left(op, self.clone(), other), self.len)
+    }
+    pub fn right(&self, op: Op, other: f64) -> Signal {
+        Signal::new(Node::Right(op, other, self.clone()), self.len)
+    }
+    pub fn shift(&self, delay: i64) -> Signal {
+        let len = self.len.saturating_add(delay).max(0);
+        Signal::new(Node::Shift(self.clone(), delay), len)
+    }
+    // 遅れのない Shift の長さを縮めて，その後を無音にする
+    pub fn take(&self, len: i64) -> Signal {
+        Signal::new(Node::Shift(self.clone(), 0), self.len.min(len.max(0)))
+    }
+
+    pub fn len(&self) -> i64 {
+        self.len
+    }
+    fn renderer(&self, rate: u32) -> Renderer {
+        Renderer::new(std::slice::from_ref(self), &HashMap::new(), rate)
+    }
+    // すべてのサンプル
+    #[cfg(test)]
+    pub fn to_vec(&self, rate: u32) -> Vec<f64> {
+        let mut samples = vec![0.; self::samples(self.len, rate)];
+        self.renderer(rate).fill(&mut samples, &[]);
+        samples
+    }
+}
+
+impl Op {
+    fn apply(self, l: f64, r: f64) -> f64 {
+        match self {
+            Op::Add => l + r,
+            Op::Sub => l - r,
+            Op::Mul => l * r,
+            Op::Div => l / r,
+            Op::Pow => l.powf(r),
+        }
+    }
+}
+
+// 節点と遅れ（全体の時刻から引くとその節点の時刻になる）
+type Key = (*const Node, i64);
+
+// グラフをブロックごとに計算する
+//
+// 節点は遅れごとに 1 回だけ計算する．
+// 入力は出力より前に並べ，同じブロックの入力のサンプルを読んで出力のサンプルを求める．
+// 計算するのは出力が 0 でないかもしれない範囲がブロックにかかる節点だけで，それ以外の出力は 0 とする
+struct Renderer {
+    instances: Vec<Instance>,
+    // その範囲の始まりの順に並べた節点（範囲が空のものは除く）と，まだ計算を始めていない最初の位置
+    starts: Vec<usize>,
+    next: usize,
+    // 計算している節点（入力が先に来るよう番号の順に並べる）
+    active: Vec<usize>,
+    // チャンネルごとの出力
+    outputs: Vec<usize>,
+    rate: u32,
+    // 全体の時刻（早める音があれば負から始め，0 までは捨てる）
+    time: isize,
+}
+
+// 節点の始まりと終わりは，全体の時刻をサンプルの境目に丸めたもの
+struct Instance {
+    signal: Signal,
+    // サンプル数で表した遅れと長さ
+    delay: isize,
+    len: usize,
+    // 出力が 0 でないかもしれない範囲
+    span: (isize, isize),
+    inputs: Vec<usize>,
+    // 他で計算したサンプルを使うなら，その番号
+    voice: Option<usize>,
+    // 位相など，前のブロックから引き継ぐ値
+    phase: f64,
+    // 計算している間だけ持つ
+    buffer: Vec<f64>,
+}
+
+impl Renderer {
+    // voices にある節点は計算せず，fill に渡されたサンプルを使う
+    fn new(channels: &[Signal], voices: &HashMap<Key, usize>, rate: u32) -> Renderer {
+        let mut renderer = Renderer {
+            instances: Vec::new(),
+            starts: Vec::new(),
+            next: 0,
+            active: Vec::new(),
+            outputs: Vec::new(),
+            rate,
+            time: 0,
+        };
+        let (mut scheduled, mut spans) = (HashMap::new(), HashMap::new());
+        for signal in channels {
+            let output = renderer.schedule(signal, 0, voices, &mut scheduled, &mut spans);
+            renderer.outputs.push(output);
+        }
+        let instances = &renderer.instances;
+        renderer.starts = (0..instances.len())
+            .filter(|&i| instances[i].span.0 < instances[i].span.1)
+            .collect();
+        renderer.starts.sort_by_key(|&i| instances[i].span.0);
+        // 前のサンプルによる値を持つのは Fm だけなので，その始まりから計算する
+        let start = renderer
+            .instances
+            .iter()
+            .filter(|instance| {
+                instance.voice.is_none() && matches!(*instance.signal.node, Node::Fm(_))
+            })
+            .map(|instance| instance.delay)
+            .min();
+        renderer.time = start.unwrap_or(0).min(0);
+        renderer.seek(0);
+        renderer
+    }
+
+    // 出力を捨てて全体の時刻 time まで進める
+    //
+    // 計算している節点がなければ，次の節点の始まりまで計算せずに飛ばす
+    fn seek(&mut self, time: isize) {
+        while self.time < time {
+            let next = self.starts.get(self.next);
+            let next = next.map_or(isize::MAX, |&i| self.instances[i].span.0);
+            if self.active.is_empty() && next > self.time {
+                self.time = next.min(time);
+                continue;
+            }
+            let n = BLOCK.min((time - self.time) as usize);
+            self.process(n, &[], 0);
+        }
+    }
+
+    // 入力を先に並べて，その位置を返す
+    fn schedule(
+        &mut self,
+        signal: &Signal,
+        delay: i64,
+        voices: &HashMap<Key, usize>,
+        scheduled: &mut HashMap<Key, usize>,
+        spans: &mut HashMap<Key, (isize, isize)>,
+    ) -> usize {
+        let key = (Arc::as_ptr(&signal.node), delay);
+        if let Some(&index) = scheduled.get(&key) {
+            return index;
+        }
+        let voice = voices.get(&key).copied();
+        let inputs = match &*signal.node {
+            _ if voice.is_some() => vec![],
+            Node::Sine(_) | Node::Line(..) | Node::Samples(..) => vec![],
+            Node::Fm(input)
+            | Node::Neg(input)
+            | Node::Recip(input)
+            | Node::Left(_, input, _)
+            | Node::Right(_, _, input) => {
+                vec![self.schedule(input, delay, voices, scheduled, spans)]
+            }
+            Node::Zip(_, left, right) => vec![
+                self.schedule(left, delay, voices, scheduled, spans),
+                self.schedule(right, delay, voices, scheduled, spans),
+            ],
+            &Node::Shift(ref input, shift) => {
+                let delay = delay.saturating_add(shift);
+                vec![self.schedule(input, delay, voices, scheduled, spans)]
+            }
+        };
+        let start = round(delay, self.rate);
+        let end = round(delay.saturating_add(signal.len), self.rate);
+        self.instances.push(Instance {
+            signal: signal.clone(),
+            delay: start,
+            len: (end - start).max(0) as usize,
+            span: span(signal, delay, self.rate, spans),
+            inputs,
+            voice,
+            phase: 0.,
+            buffer: Vec::new(),
+        });
+        scheduled.insert(key, self.instances.len() - 1);
+        self.instances.len() - 1
+    }
+
+    // 続くフレームで埋める（終わった後は 0）
+    //
+    // out はチャンネルごとのサンプルを交互に並べる．voices は声部ごとの，out と同じ時刻のサンプル
+    fn fill(&mut self, out: &mut [f64], voices: &[&[f64]]) {
+        let channels = self.outputs.len();
+        for (i, chunk) in out.chunks_mut(BLOCK * channels).enumerate() {
+            let n = chunk.len() / channels;
+            self.process(n, voices, i * BLOCK);
+            for (channel, &output) in self.outputs.iter().enumerate() {
+                let buffer = &self.instances[output].output()[..n];
+                for (frame, &x) in chunk.chunks_mut(channels).zip(buffer) {
+                    frame[channel] = x;
+                }
+            }
+        }
+    }
+
+    // 全体の時刻から n サンプルを計算する（声部のサンプルは offset から読む）
+    fn process(&mut self, n: usize, voices: &[&[f64]], offset: usize) {
+        // 前のブロックまでに終わった節点は，ここからは 0 を出力する
+        let (time, instances) = (self.time, &mut self.instances);
+        self.active.retain(|&i| {
+            let instance = &mut instances[i];
+            let done = instance.span.1 <= time;
+            if done {
+                instance.buffer = Vec::new();
+            }
+            !done
+        });
+        let end = time + n as isize;
+        while let Some(&i) = self.starts.get(self.next) {
+            if self.instances[i].span.0 >= end {
+                break;
+            }
+            self.instances[i].buffer = vec![0.; BLOCK];
+            let at = self.active.binary_search(&i).unwrap_err();
+            self.active.insert(at, i);
+            self.next += 1;
+        }
+        for &i in &self.active {
+            let (inputs, rest) = self.instances.split_at_mut(i);
+            rest[0].process(self.time, n, self.rate, inputs, voices, offset);
+        }
+        self.time = end;
+    }
+}
+
+impl Instance {
+    fn output(&self) -> &[f64] {
+        if self.buffer.is_empty() {
+            &SILENCE
+        } else {
+            &self.buffer
+        }
+    }
+
+    fn process(
+        &mut self,
+        time: isize,
+        n: usize,
+        rate: u32,
+        instances: &[Instance],
+        voices: &[&[f64]],
+        offset: usize,
+    ) {
+        if let Some(voice) = self.voice {
+            self.buffer[..n].copy_from_slice(&voices[voice][offset..offset + n]);
+            return;
+        }
+        // 節点の時刻が [0, len) にあるサンプルだけを計算する
+        let start = time - self.delay;
+        let begin = (-start).clamp(0, n as isize) as usize;
+        let end = (self.len as isize - start).clamp(begin as isize, n as isize) as usize;
+        self.buffer[..begin].fill(0.);
+        self.buffer[end..n].fill(0.);
+        let input = |k: usize| &instances[self.inputs[k]].output()[begin..end];
+        let out = &mut self.buffer[begin..end];
+        let step = std::f64::consts::TAU / rate as f64;
+        let local = (start + begin as isize) as usize;
+        match *self.signal.node {
+            Node::Sine(frequency) => {
+                for (j, y) in out.iter_mut().enumerate() {
+                    *y = (step * frequency * (local + j) as f64).sin();
+                }
+            }
+            Node::Fm(_) => {
+                for (y, &frequency) in out.iter_mut().zip(input(0)) {
+                    *y = self.phase.sin();
+                    self.phase += step * frequency;
+                }
+            }
+            Node::Line(from, to) => {
+                let len = self.len as f64;
+                for (j, y) in out.iter_mut().enumerate() {
+                    *y = from + (to - from) * (local + j) as f64 / len;
+                }
+            }
+            Node::Samples(ref samples, native) if native == rate => {
+                for (j, y) in out.iter_mut().enumerate() {
+                    *y = samples.get(local + j).copied().unwrap_or(0.);
+                }
+            }
+            Node::Samples(ref samples, native) => {
+                let ratio = native as f64 / rate as f64;
+                for (j, y) in out.iter_mut().enumerate() {
+                    *y = interpolate(samples, (local + j) as f64 * ratio);
+                }
+            }
+            Node::Neg(_) => map(out, input(0), |x| -x),
+            Node::Recip(_) => map(out, input(0), |x| 1. / x),
+            Node::Zip(op, ..) => {
+                for ((y, &l), &r) in out.iter_mut().zip(input(0)).zip(input(1)) {
+                    *y = op.apply(l, r);
+                }
+            }
+            Node::Left(op, _, r) => map(out, input(0), |l| op.apply(l, r)),
+            Node::Right(op, l, _) => map(out, input(0), |r| op.apply(l, r)),
+            Node::Shift(..) => out.copy_from_slice(input(0)),
+        }
+    }
+}
+
+// 節点の出力が 0 でないかもしれないサンプルの範囲
+//
+// 入力がすべて 0 なら 0 を出力する節点は，入力の範囲を合わせたところまで縮める
+fn span(
+    signal: &Signal,
+    delay: i64,
+    rate: u32,
+    spans: &mut HashMap<Key, (isize, isize)>,
+) -> (isize, isize) {
+    let key = (Arc::as_ptr(&signal.node), delay);
+    if let Some(&span) = spans.get(&key) {
+        return span;
+    }
+    let start = round(delay, rate);
+    let end = round(delay.saturating_add(signal.len), rate).max(start);
+    let (from, to) = match &*signal.node {
+        &Node::Shift(ref input, shift) => span(input, delay.saturating_add(shift), rate, spans),
+        Node::Zip(Op::Add | Op::Sub | Op::Mul, left, right) => {
+            let left = span(left, delay, rate, spans);
+            let right = span(right, delay, rate, spans);
+            (left.0.min(right.0), left.1.max(right.1))
+        }
+        _ => (start, end),
+    };
+    let start = start.max(from);
+    let span = (start, end.min(to).max(start));
+    spans.insert(key, span);
+    span
+}
+
+fn map(out: &mut [f64], input: &[f64], f: impl Fn(f64) -> f64) {
+    for (y, &x) in out.iter_mut().zip(input) {
+        *y = f(x);
+    }
+}
+
+// サンプルの間の位置 x の値を前後 4 つのサンプルから 3 次式で補間する（範囲の外は 0）
+fn interpolate(samples: &[f64], x: f64) -> f64 {
+    let i = x.floor() as usize;
+    let t = x - i as f64;
+    let get = |k: usize| samples.get(k).copied().unwrap_or(0.);
+    let (y0, y1, y2, y3) = (
+        i.checked_sub(1).map_or(0., get),
+        get(i),
+        get(i + 1),
+        get(i + 2),
+    );
+    let c1 = 0.5 * (y2 - y0);
+    let c2 = y0 - 2.5 * y1 + 2. * y2 - 0.5 * y3;
+    let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+    ((c3 * t + c2) * t + c1) * t + y1
+}
+
+// 秒数を TICKS を単位とする時刻に丸める
+pub fn ticks(seconds: f64) -> i64 {
+    (seconds * TICKS as f64).round() as i64
+}
+
+// 時刻をいちばん近いサンプルの境目の番号に丸める
+fn round(ticks: i64, rate: u32) -> isize {
+    let ticks = ticks as i128 * rate as i128;
+    (ticks * 2 + TICKS as i128).div_euclid(TICKS as i128 * 2) as isize
+}
+
+// 長さのサンプル数
+fn samples(len: i64, rate: u32) -> usize {
+    round(len, rate).max(0) as usize
+}
@@ -0,0 +1,144 @@
+// 足し合わされた信号（声部）を別々のスレッドで計算する
+//
+// 声部を足し合わせるところは 1 つのスレッドで元のグラフのとおりに計算するので，
+// スレッド数によらず同じサンプルになる．声部は鳴っている範囲にかかるチャンクだけを計算して渡す
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::mpsc;
+use std::sync::Arc;
+
+use super::{samples, span, Key, Node, Op, Renderer, Signal, BLOCK};
+
+// 一度にスレッドの間で受け渡すフレーム数
+const CHUNK: usize = BLOCK * 16;
+
+// 始めから順に，CHUNK ずつのフレームを f に渡す（チャンネルごとのサンプルを交互に並べる）
+pub fn render(
+    channels: &[Signal],
+    threads: usize,
+    rate: u32,
+    mut f: impl FnMut(&[f64]) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut voices = Vec::new();
+    let mut keys = HashMap::new();
+    for signal in channels {
+        if !split(signal, 0, &mut voices, &mut keys) {
+            add_voice(signal, 0, &mut voices, &mut keys);
+        }
+    }
+    let mut mixer = Renderer::new(channels, &keys, rate);
+    let threads = threads.clamp(1, voices.len());
+    let len = samples(channels.iter().map(Signal::len).max().unwrap_or(0), rate);
+    let chunks = len.div_ceil(CHUNK);
+    let chunk_len = |i: usize| CHUNK.min(len - i * CHUNK);
+    // 声部が鳴っている範囲（この外は 0 で，混ぜる側も読まない）
+    let mut spans = HashMap::new();
+    let mut voices: Vec<_> = voices
+        .into_iter()
+        .enumerate()
+        .map(|(index, voice)| (index, span(&voice, 0, rate, &mut spans), voice))
+        .filter(|&(_, (start, end), _)| start < end)
+        .collect();
+    voices.sort_by_key(|&(_, (start, _), _)| start);
+    std::thread::scope(|scope| {
+        // 声部を始まりの順に，スレッドへ順番に割り振る
+        let workers: Vec<_> = (0..threads)
+            .map(|k| {
+                let voices: Vec<_> = voices.iter().skip(k).step_by(threads).collect();
+                // 1 チャンクより先には進まない．使い終わったサンプルの列は送り返してもらう
+                let (sender, receiver) = mpsc::sync_channel(1);
+                let (recycle, recycled) = mpsc::channel::<Vec<f64>>();
+                scope.spawn(move || {
+                    let mut next = 0;
+                    let mut active = Vec::new();
+                    for i in 0..chunks {
+                        let begin = (i * CHUNK) as isize;
+                        let end = begin + chunk_len(i) as isize;
+                        while let Some(&&(index, (start, stop), ref voice)) = voices.get(next) {
+                            if start >= end {
+                                break;
+                            }
+                            let mut renderer = voice.renderer(rate);
+                            renderer.seek(begin);
+                            active.push((index, stop, renderer));
+                            next += 1;
+                        }
+                        let chunk: Vec<_> = active
+                            .iter_mut()
+                            .map(|(index, _, renderer)| {
+                                let mut samples = recycled.try_recv().unwrap_or_default();
+                                samples.clear();
+                                samples.resize(chunk_len(i), 0.);
+                                renderer.fill(&mut samples, &[]);
+                                (*index, samples)
+                            })
+                            .collect();
+                        active.retain(|&(_, stop, _)| stop > end);
+                        // 受け取る側が書き出しに失敗して止まっていれば終わる
+                        if sender.send(chunk).is_err() {
+                            return;
+                        }
+                    }
+                });
+                (receiver, recycle)
+            })
+            .collect();
+        for i in 0..chunks {
+            let chunks: Vec<Vec<(usize, Vec<f64>)>> = workers
+                .iter()
+                .map(|(receiver, _)| receiver.recv().unwrap())
+                .collect();
+            let mut voices = vec![&[][..]; keys.len()];
+            for &(index, ref samples) in chunks.iter().flatten() {
+                voices[index] = samples;
+            }
+            let mut samples = vec![0.; chunk_len(i) * channels.len()];
+            mixer.fill(&mut samples, &voices);
+            f(&samples)?;
+            for ((_, recycle), chunk) in workers.iter().zip(chunks) {
+                for (_, samples) in chunk {
+                    // 計算し終えたスレッドには返さなくてよい
+                    let _ = recycle.send(samples);
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+// `+` の被演算子を声部として集める（`+` が見つからなければ false）
+//
+// 数との演算や遅れはたどり，その先に `+` がなければその信号ごと声部にする
+fn split(
+    sound: &Signal,
+    delay: i64,
+    voices: &mut Vec<Signal>,
+    keys: &mut HashMap<Key, usize>,
+) -> bool {
+    match &*sound.node {
+        Node::Zip(Op::Add, left, right) => {
+            for operand in [left, right] {
+                if !split(operand, delay, voices, keys) {
+                    add_voice(operand, delay, voices, keys);
+                }
+            }
+            true
+        }
+        Node::Neg(input)
+        | Node::Recip(input)
+        | Node::Left(_, input, _)
+        | Node::Right(_, _, input) => split(input, delay, voices, keys),
+        &Node::Shift(ref input, shift) => split(input, delay.saturating_add(shift), voices, keys),
+        _ => false,
+    }
+}
+
+// 声部は全体の時刻で計算できるよう遅らせておく
+fn add_voice(sound: &Signal, delay: i64, voices: &mut Vec<Signal>, keys: &mut HashMap<Key, usize>) {
+    keys.entry((Arc::as_ptr(&sound.node), delay))
+        .or_insert_with(|| {
+            voices.push(sound.shift(delay));
+            voices.len() - 1
+        });
+}
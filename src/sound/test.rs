@@ -0,0 +1,215 @@
+#![cfg(test)]
+
+use super::{ticks, Op, Signal, Sound, BLOCK, SAMPLE_RATE, TICKS};
+
+// 既定のサンプリング周波数での 1 サンプルの長さ
+const S: i64 = TICKS / SAMPLE_RATE as i64;
+
+#[test]
+fn shift_and_zip() {
+    let sound = Signal::line(1., 4., 3 * S);
+    let to_vec = |signal: Signal| signal.to_vec(SAMPLE_RATE);
+    assert_eq!(to_vec(sound.shift(2 * S)), [0., 0., 1., 2., 3.]);
+    assert_eq!(to_vec(sound.shift(-S)), [2., 3.]);
+    assert_eq!(to_vec(sound.shift(-5 * S)), [] as [f64; 0]);
+    let short = Signal::line(10., 10., S);
+    assert_eq!(to_vec(sound.zip(Op::Add, &short)), [11., 2., 3.]);
+    assert_eq!(to_vec(sound.shift(S).take(2 * S)), [0., 1.]);
+    assert_eq!(to_vec(sound.take(5 * S)), [1., 2., 3.]);
+    assert_eq!(ticks(0.5), TICKS / 2);
+}
+
+// 遅れは足し合わせてもずれず，書き出すときにいちばん近いサンプルに丸める
+#[test]
+fn alignment() {
+    let sound = Signal::line(1., 4., 3 * S);
+    let a = sound.shift(ticks(0.1)).shift(ticks(0.2));
+    let b = sound.shift(ticks(0.3));
+    assert_eq!(a.len(), b.len());
+    for rate in [8000, 44100, 48000, 96000] {
+        let samples = a.to_vec(rate);
+        assert_eq!(samples, b.to_vec(rate));
+        assert_eq!(
+            samples.iter().position(|&x| x != 0.),
+            Some(rate as usize * 3 / 10)
+        );
+    }
+    // サンプルの途中から始まる音
+    let half = sound.shift(S / 2 - 1);
+    assert_eq!(half.to_vec(SAMPLE_RATE), [1., 2., 3.]);
+}
+
+// ブロックの境目をまたいでも，まとめて計算したのと同じになる
+#[test]
+fn blocks() {
+    let len = BLOCK * 3 + 5;
+    let fm = Signal::fm(&Signal::line(0., 1000., len as i64 * S), len as i64 * S);
+    let to_vec = |signal: Signal| signal.to_vec(SAMPLE_RATE);
+    let samples = to_vec(fm.clone());
+    assert_eq!(samples.len(), len);
+    let skip = BLOCK + 7;
+    assert_eq!(to_vec(fm.shift(-(skip as i64) * S)), samples[skip..]);
+    // 共有された節点
+    let twice = to_vec(fm.zip(Op::Add, &fm));
+    let doubled = to_vec(fm.left(Op::Mul, 2.));
+    assert_eq!(twice, doubled);
+    let delayed = to_vec(fm.shift(3 * S).zip(Op::Sub, &fm.shift(-2 * S).shift(5 * S)));
+    assert!(delayed.iter().all(|&x| x == 0.));
+}
+
+// 続けて鳴らす音は，鳴っている音とそれを足し合わせる節点だけを計算する
+#[test]
+fn schedule() {
+    let note = Signal::line(1., 1., BLOCK as i64 * S);
+    let mut sounds: Vec<_> = (0..64).map(|i| note.shift(i * note.len())).collect();
+    while sounds.len() > 1 {
+        sounds = sounds
+            .chunks(2)
+            .map(|pair| pair[0].zip(Op::Add, &pair[1]))
+            .collect();
+    }
+    let mut renderer = sounds[0].renderer(SAMPLE_RATE);
+    let mut samples = [0.; BLOCK];
+    for _ in 0..64 {
+        renderer.fill(&mut samples, &[]);
+        assert!(samples.iter().all(|&x| x == 1.));
+        // Line と Shift，根までの 6 つの Zip
+        assert_eq!(renderer.active.len(), 8);
+    }
+}
+
+#[test]
+fn wav() {
+    let nan = Signal::line(0., 0., S).recip().left(Op::Mul, 0.);
+    let sound = Signal::line(0., 3., 3 * S).zip(Op::Mul, &Signal::line(3., -3., 3 * S));
+    let sound = sound.zip(Op::Add, &nan.shift(3 * S));
+    let mut out = Vec::new();
+    Sound::mono(sound)
+        .write_wav(&mut out, 1, SAMPLE_RATE)
+        .unwrap();
+    assert_eq!(out.len(), 44 + 8);
+    assert_eq!(&out[0..4], b"RIFF");
+    assert_eq!(&out[4..8], &(36u32 + 8).to_le_bytes());
+    assert_eq!(&out[24..28], &SAMPLE_RATE.to_le_bytes());
+    assert_eq!(&out[40..44], &8u32.to_le_bytes());
+    assert_eq!(&out[44..], &[0, 0, 0xff, 0x7f, 0x01, 0x80, 0, 0]);
+}
+
+// ヘッダーの欄に収まらなければ何も書かない
+#[test]
+fn wav_too_large() {
+    let silence = Signal::line(0., 0., S);
+    let stereo = Sound::from_channels(vec![silence.clone(), silence]);
+    let mut out = Vec::new();
+    let err = stereo.write_wav(&mut out, 1, u32::MAX / 2).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    // 4 GiB を超えるデータ
+    let long = Sound::mono(Signal::line(0., 0., TICKS * (1 << 16)));
+    let err = long.write_wav(&mut out, 1, SAMPLE_RATE).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(out.is_empty());
+}
+
+// スレッド数によらず同じサンプルになる
+#[test]
+fn threads() {
+    let len = (BLOCK * 40 + 3) as i64 * S;
+    let voice = |i: i64| {
+        let frequency = Signal::line(100. * i as f64, 50., len);
+        Signal::fm(&frequency, len - i * 100 * S).left(Op::Mul, 0.1)
+    };
+    let mut sound = voice(0);
+    for i in 1..7 {
+        // サンプルの境目からずれた遅れも含める
+        let voice = voice(i).shift((i * 1000 - 3000) * S + i * 3000);
+        sound = if i % 3 == 0 {
+            voice.zip(Op::Add, &sound)
+        } else {
+            sound.zip(Op::Add, &voice)
+        };
+    }
+    // 足し合わせた後の演算も同じ順に計算する
+    let sound = sound.left(Op::Mul, 0.5).neg().shift(-500 * S);
+    let expected = sound.to_vec(48000);
+    for threads in [1, 2, 3, 8] {
+        let mut samples = Vec::new();
+        super::parallel::render(std::slice::from_ref(&sound), threads, 48000, |chunk| {
+            samples.extend_from_slice(chunk);
+            Ok(())
+        })
+        .unwrap();
+        let bits = |samples: &[f64]| samples.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&samples), bits(&expected), "{threads} threads");
+    }
+}
+
+// 途中から鳴る声部は，その前のチャンクを計算せずに飛ばしても同じサンプルになる
+#[test]
+fn sequence() {
+    let len = (BLOCK * 5 + 11) as i64 * S;
+    let frequency = Signal::line(200., 900., 2 * len);
+    // 始めを捨てた Fm は，捨てたところから位相を進めておく
+    let note = Signal::fm(&frequency, 2 * len)
+        .shift(-len)
+        .left(Op::Mul, 0.1);
+    let mut sound = note.clone();
+    for i in 1..40 {
+        sound = sound.zip(Op::Add, &note.shift(i * (len + 7 * S) + i * 5000));
+    }
+    let expected = sound.to_vec(48000);
+    for threads in [1, 3] {
+        let mut samples = Vec::new();
+        super::parallel::render(std::slice::from_ref(&sound), threads, 48000, |chunk| {
+            samples.extend_from_slice(chunk);
+            Ok(())
+        })
+        .unwrap();
+        let bits = |samples: &[f64]| samples.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&samples), bits(&expected), "{threads} threads");
+    }
+}
+
+#[test]
+fn stereo_wav() {
+    let left = Signal::line(0., 2., 2 * S);
+    let right = Signal::line(-1., -1., S);
+    let mut out = Vec::new();
+    Sound::from_channels(vec![left, right])
+        .write_wav(&mut out, 2, SAMPLE_RATE)
+        .unwrap();
+    assert_eq!(out.len(), 44 + 8);
+    // チャンネル数，1 秒あたりのバイト数，1 フレームのバイト数
+    assert_eq!(&out[22..24], &2u16.to_le_bytes());
+    assert_eq!(&out[28..32], &(SAMPLE_RATE * 4).to_le_bytes());
+    assert_eq!(&out[32..34], &4u16.to_le_bytes());
+    assert_eq!(&out[44..], &[0, 0, 0x01, 0x80, 0xff, 0x7f, 0, 0]);
+}
+
+// 書き出したサンプリング周波数で読み戻し，別の周波数で書き出すと補間する
+#[test]
+fn resample() {
+    let sound = Sound::from_channels(vec![
+        Signal::sine(440., TICKS / 10),
+        Signal::line(0.5, -0.5, TICKS / 10),
+    ]);
+    let mut out = Vec::new();
+    sound.write_wav(&mut out, 1, 48000).unwrap();
+    assert_eq!(&out[24..28], &48000u32.to_le_bytes());
+    assert_eq!(out.len(), 44 + 4800 * 4);
+    let read = Sound::read_wav(&mut &out[..]).unwrap();
+    assert_eq!(read.channels().len(), 2);
+    assert_eq!(read.len(), TICKS / 10);
+    // 同じ周波数ならそのまま
+    let mut again = Vec::new();
+    read.write_wav(&mut again, 1, 48000).unwrap();
+    assert_eq!(again, out);
+    for (read, signal) in read.channels().iter().zip(sound.channels()) {
+        let resampled = read.to_vec(SAMPLE_RATE);
+        assert_eq!(resampled.len(), 4410);
+        let expected = signal.to_vec(SAMPLE_RATE);
+        for (x, y) in resampled.iter().zip(&expected).skip(1).take(4400) {
+            assert!((x - y).abs() < 1e-3, "{x} {y}");
+        }
+    }
+    assert!(Sound::read_wav(&mut &b"RIFF"[..]).is_err());
+}
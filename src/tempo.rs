@@ -0,0 +1,88 @@
+// テンポの移り変わり（拍の位置を秒数に直す）
+
+mod test;
+
+// 既定のテンポ（1 分あたりの拍数）と 1 小節の拍数
+const BPM: f64 = 120.;
+const METER: f64 = 4.;
+
+#[derive(Clone)]
+pub struct Tempo {
+    // 拍の位置の順に並べた変わり目（始めは 0 拍目）
+    changes: Vec<Change>,
+    meter: f64,
+}
+
+#[derive(Clone, Copy)]
+struct Change {
+    beat: f64,
+    bpm: f64,
+    // 前の変わり目からこのテンポまで直線的に変える
+    ramp: bool,
+}
+
+impl Tempo {
+    pub fn new() -> Tempo {
+        Tempo {
+            changes: vec![Change {
+                beat: 0.,
+                bpm: BPM,
+                ramp: false,
+            }],
+            meter: METER,
+        }
+    }
+
+    // 始めから終わりまで一定のテンポにする
+    pub fn set(&mut self, bpm: f64) {
+        self.changes.truncate(1);
+        self.changes[0].bpm = bpm;
+    }
+    // beat 拍目からテンポを変える（同じ位置の変わり目は置き換える）
+    //
+    // bpm は正，beat は 0 以上であること
+    pub fn change(&mut self, beat: f64, bpm: f64, ramp: bool) {
+        let change = Change {
+            beat,
+            bpm,
+            ramp: ramp && beat > 0.,
+        };
+        match self.changes.binary_search_by(|c| c.beat.total_cmp(&beat)) {
+            Ok(i) => self.changes[i] = change,
+            Err(i) => self.changes.insert(i, change),
+        }
+    }
+    pub fn meter(&self) -> f64 {
+        self.meter
+    }
+    pub fn set_meter(&mut self, beats: f64) {
+        self.meter = beats;
+    }
+
+    // 0 拍目から beat 拍目までの秒数（負の拍は始めのテンポで数える）
+    pub fn seconds(&self, beat: f64) -> f64 {
+        let first = self.changes[0];
+        if beat < 0. {
+            return 60. * beat / first.bpm;
+        }
+        let mut seconds = 0.;
+        for (i, change) in self.changes.iter().enumerate() {
+            if beat <= change.beat {
+                break;
+            }
+            let next = self.changes.get(i + 1);
+            let end = next.map_or(beat, |next| next.beat.min(beat));
+            let beats = end - change.beat;
+            seconds += match next {
+                // テンポが拍について 1 次式なら，1 拍の秒数を積分すると対数になる
+                Some(next) if next.ramp && next.bpm != change.bpm => {
+                    let slope = (next.bpm - change.bpm) / (next.beat - change.beat);
+                    let bpm = change.bpm + slope * beats;
+                    60. / slope * (bpm / change.bpm).ln()
+                }
+                _ => 60. * beats / change.bpm,
+            };
+        }
+        seconds
+    }
+}
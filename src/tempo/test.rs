@@ -0,0 +1,46 @@
+#![cfg(test)]
+
+use super::Tempo;
+
+fn close(x: f64, y: f64) -> bool {
+    (x - y).abs() < 1e-12
+}
+
+#[test]
+fn constant() {
+    let mut tempo = Tempo::new();
+    assert_eq!(tempo.seconds(4.), 2.);
+    tempo.set(90.);
+    assert_eq!(tempo.seconds(3.), 2.);
+    assert_eq!(tempo.seconds(-1.5), -1.);
+}
+
+#[test]
+fn changes() {
+    let mut tempo = Tempo::new();
+    tempo.change(4., 60., false);
+    assert_eq!(tempo.seconds(4.), 2.);
+    assert_eq!(tempo.seconds(6.), 4.);
+    // 後から前に変わり目を入れる
+    tempo.change(2., 240., false);
+    assert_eq!(tempo.seconds(4.), 1.5);
+    assert_eq!(tempo.seconds(6.), 3.5);
+    // 始めから一定にすると変わり目は消える
+    tempo.set(60.);
+    assert_eq!(tempo.seconds(6.), 6.);
+}
+
+// 60 から 120 まで 4 拍で直線的に速める
+#[test]
+fn ramp() {
+    let mut tempo = Tempo::new();
+    tempo.set(60.);
+    tempo.change(4., 120., true);
+    let seconds = 4. * 2f64.ln();
+    assert!(close(tempo.seconds(4.), seconds));
+    assert!(close(tempo.seconds(2.), 4. * 1.5f64.ln()));
+    assert!(close(tempo.seconds(6.), seconds + 1.));
+    // 始めの拍より前からは変えられない
+    tempo.change(0., 60., true);
+    assert!(close(tempo.seconds(4.), seconds));
+}
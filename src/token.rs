@@ -1,3 +1,4 @@
+#[derive(Clone, Debug, PartialEq)]
 pub enum Token {
     Identifier(String),
     BinInt(String),
@@ -9,6 +10,11 @@ pub enum Token {
     KeywordFor,
     KeywordIf,
     KeywordElse,
+    KeywordImport,
+    KeywordAs,
+    KeywordReturn,
+    KeywordTrue,
+    KeywordFalse,
     Plus,
     DoublePlus,
     PlusEqual,
@@ -17,6 +23,8 @@ pub enum Token {
     HyphenEqual,
     Asterisk,
     AsteriskEqual,
+    DoubleAsterisk,
+    DoubleAsteriskEqual,
     Slash,
     SlashEqual,
     Percent,
@@ -82,12 +90,124 @@ impl Token {
     pub fn is_string(&self) -> bool {
         matches!(self, Self::String(..))
     }
+    pub fn is_keyword_import(&self) -> bool {
+        matches!(self, Self::KeywordImport)
+    }
+    pub fn is_keyword_as(&self) -> bool {
+        matches!(self, Self::KeywordAs)
+    }
+    pub fn is_keyword_return(&self) -> bool {
+        matches!(self, Self::KeywordReturn)
+    }
     pub fn is_opening_parenthesis(&self) -> bool {
         matches!(self, Self::OpeningParenthesis)
     }
+    pub fn is_opening_bracket(&self) -> bool {
+        matches!(self, Self::OpeningBracket)
+    }
+    pub fn is_opening_brace(&self) -> bool {
+        matches!(self, Self::OpeningBrace)
+    }
+    pub fn is_closing_brace(&self) -> bool {
+        matches!(self, Self::ClosingBrace)
+    }
+    pub fn is_dot(&self) -> bool {
+        matches!(self, Self::Dot)
+    }
+    pub fn is_question(&self) -> bool {
+        matches!(self, Self::Question)
+    }
+    pub fn is_colon(&self) -> bool {
+        matches!(self, Self::Colon)
+    }
     pub fn is_comma(&self) -> bool {
         matches!(self, Self::Comma)
     }
 }
 
+impl Token {
+    // トークンの種類の名前（`--tokens-json` で出力する）
+    //
+    // derive した Debug の出力から値を除いた変種の名前
+    pub fn kind(&self) -> String {
+        let mut name = format!("{self:?}");
+        if let Some(index) = name.find('(') {
+            name.truncate(index);
+        }
+        name
+    }
+}
+
+use std::fmt::{self, Display, Formatter};
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Token::Identifier(s) | Token::DecInt(s) | Token::Float(s) => write!(f, "{s}"),
+            Token::BinInt(s) => write!(f, "0b{s}"),
+            Token::OctInt(s) => write!(f, "0o{s}"),
+            Token::HexInt(s) => write!(f, "0x{s}"),
+            Token::String(s) => write!(f, "{s:?}"),
+            Token::KeywordFor => f.write_str("for"),
+            Token::KeywordIf => f.write_str("if"),
+            Token::KeywordElse => f.write_str("else"),
+            Token::KeywordImport => f.write_str("import"),
+            Token::KeywordAs => f.write_str("as"),
+            Token::KeywordReturn => f.write_str("return"),
+            Token::KeywordTrue => f.write_str("true"),
+            Token::KeywordFalse => f.write_str("false"),
+            Token::Plus => f.write_str("+"),
+            Token::DoublePlus => f.write_str("++"),
+            Token::PlusEqual => f.write_str("+="),
+            Token::Hyphen => f.write_str("-"),
+            Token::DoubleHyphen => f.write_str("--"),
+            Token::HyphenEqual => f.write_str("-="),
+            Token::Asterisk => f.write_str("*"),
+            Token::AsteriskEqual => f.write_str("*="),
+            Token::DoubleAsterisk => f.write_str("**"),
+            Token::DoubleAsteriskEqual => f.write_str("**="),
+            Token::Slash => f.write_str("/"),
+            Token::SlashEqual => f.write_str("/="),
+            Token::Percent => f.write_str("%"),
+            Token::PercentEqual => f.write_str("%="),
+            Token::Equal => f.write_str("="),
+            Token::DoubleEqual => f.write_str("=="),
+            Token::Exclamation => f.write_str("!"),
+            Token::ExclamationEqual => f.write_str("!="),
+            Token::Less => f.write_str("<"),
+            Token::LessEqual => f.write_str("<="),
+            Token::DoubleLess => f.write_str("<<"),
+            Token::DoubleLessEqual => f.write_str("<<="),
+            Token::TripleLess => f.write_str("<<<"),
+            Token::TripleLessEqual => f.write_str("<<<="),
+            Token::Greater => f.write_str(">"),
+            Token::GreaterEqual => f.write_str(">="),
+            Token::DoubleGreater => f.write_str(">>"),
+            Token::DoubleGreaterEqual => f.write_str(">>="),
+            Token::TripleGreater => f.write_str(">>>"),
+            Token::TripleGreaterEqual => f.write_str(">>>="),
+            Token::Ampersand => f.write_str("&"),
+            Token::AmpersandEqual => f.write_str("&="),
+            Token::DoubleAmpersand => f.write_str("&&"),
+            Token::Bar => f.write_str("|"),
+            Token::BarEqual => f.write_str("|="),
+            Token::DoubleBar => f.write_str("||"),
+            Token::Circumflex => f.write_str("^"),
+            Token::CircumflexEqual => f.write_str("^="),
+            Token::Dot => f.write_str("."),
+            Token::Colon => f.write_str(":"),
+            Token::Semicolon => f.write_str(";"),
+            Token::Comma => f.write_str(","),
+            Token::Question => f.write_str("?"),
+            Token::Hash => f.write_str("#"),
+            Token::Tilde => f.write_str("~"),
+            Token::OpeningParenthesis => f.write_str("("),
+            Token::ClosingParenthesis => f.write_str(")"),
+            Token::OpeningBracket => f.write_str("["),
+            Token::ClosingBracket => f.write_str("]"),
+            Token::OpeningBrace => f.write_str("{"),
+            Token::ClosingBrace => f.write_str("}"),
+        }
+    }
+}
+
 pub type PToken = (crate::pos::Range, Token);
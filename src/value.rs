@@ -0,0 +1,137 @@
+use std::fmt::{self, Display, Formatter};
+use std::rc::Rc;
+
+use crate::error::Error;
+use crate::eval::{compile, Context, Scope};
+use crate::pos;
+use crate::sentence;
+use crate::sound::Sound;
+
+#[derive(Clone)]
+pub enum Value {
+    // 値を持たない（`print` の戻り値など）
+    Void,
+    Boolean(bool),
+    Integer(i32),
+    Float(f64),
+    String(Rc<str>),
+    // 代入すると複製される（書き換えるときに共有していれば複製する）
+    List(Rc<Vec<Value>>),
+    Record(Rc<Record>),
+    Sound(Rc<Sound>),
+    Module(Rc<Module>),
+    Builtin(Builtin),
+    Function(Rc<Function>),
+}
+
+// 名前の付いた値の組（フィールドは書いた順に並べる）
+#[derive(Clone)]
+pub struct Record {
+    pub fields: Vec<(String, Value)>,
+}
+
+impl Record {
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find_map(|(field, value)| (field == name).then_some(value))
+    }
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.fields
+            .iter_mut()
+            .find_map(|(field, value)| (field == name).then_some(value))
+    }
+}
+
+// `import` で読み込んだファイルの名前空間
+pub struct Module {
+    pub name: String,
+    pub scope: Rc<Scope>,
+}
+
+// ユーザー定義の関数
+//
+// 定義された場所の有効範囲を持ち，本体はその内側で実行する
+pub struct Function {
+    pub definition: Rc<sentence::Function>,
+    pub scope: Rc<Scope>,
+    // バイトコードで定義されたときはその翻訳
+    pub code: Option<Rc<compile::Function>>,
+}
+
+// 組み込み関数
+//
+// 組み込み関数の状態，呼び出し全体の位置と，引数の並びに揃えた位置付きの引数を受け取る
+// （省略された引数は None）
+#[derive(Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    // 引数の名前と省略できるかどうか．None なら任意個の位置による引数をとる
+    pub params: Option<&'static [(&'static str, bool)]>,
+    pub fnc: BuiltinFn,
+}
+
+pub type BuiltinFn = fn(&mut Context, &pos::Range, Vec<Option<PValue>>) -> Result<Value, Error>;
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Void => "void",
+            Value::Boolean(_) => "boolean",
+            Value::Integer(_) => "integer",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Record(_) => "record",
+            Value::Sound(_) => "sound",
+            Value::Module(_) => "module",
+            Value::Builtin(_) => "builtin function",
+            Value::Function(_) => "function",
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::Void => write!(f, "void"),
+            Value::Boolean(value) => write!(f, "{value}"),
+            Value::Integer(n) => write!(f, "{n}"),
+            Value::Float(x) => write!(f, "{x:?}"),
+            Value::String(s) => write!(f, "{s}"),
+            Value::List(elems) => {
+                f.write_str("[")?;
+                for (i, elem) in elems.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    match elem {
+                        Value::String(s) => write!(f, "{s:?}")?,
+                        elem => write!(f, "{elem}")?,
+                    }
+                }
+                f.write_str("]")
+            }
+            Value::Record(record) => {
+                f.write_str("{")?;
+                for (i, (name, value)) in record.fields.iter().enumerate() {
+                    f.write_str(if i > 0 { ", " } else { " " })?;
+                    match value {
+                        Value::String(s) => write!(f, "{name}: {s:?}")?,
+                        value => write!(f, "{name}: {value}")?,
+                    }
+                }
+                f.write_str(if record.fields.is_empty() { "}" } else { " }" })
+            }
+            Value::Sound(sound) => match sound.channels().len() {
+                1 => write!(f, "<sound {:?}s>", sound.duration()),
+                n => write!(f, "<sound {:?}s, {n} channels>", sound.duration()),
+            },
+            Value::Module(module) => write!(f, "<module {}>", module.name),
+            Value::Builtin(builtin) => write!(f, "<builtin {}>", builtin.name),
+            Value::Function(function) => write!(f, "<function {}>", function.definition.name.1),
+        }
+    }
+}
+
+pub type PValue = (pos::Range, Value);